@@ -0,0 +1,112 @@
+use std::fs;
+use std::convert::TryInto;
+
+use nix::unistd::Pid;
+
+
+//the auxv entries the debugger cares about. See getauxval(3)
+pub const AT_PHDR: u64 = 3;
+pub const AT_BASE: u64 = 7;
+pub const AT_ENTRY: u64 = 9;
+
+//(type, value) pairs of the auxiliary vector the kernel gave the process
+pub fn read_auxv(pid: Pid) -> Vec<(u64, u64)> {
+	match fs::read(format!("/proc/{}/auxv", pid)) {
+		Ok(data) => parse_auxv(&data),
+		Err(_) => Vec::new(),
+	}
+}
+
+//raw auxv, as in /proc/<pid>/auxv or the NT_AUXV note of a core file
+pub fn parse_auxv(data: &[u8]) -> Vec<(u64, u64)> {
+	let mut auxv = Vec::new();
+	for entry in data.chunks_exact(16) {
+		let a_type = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+		let a_val = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+		//AT_NULL ends it
+		if a_type == 0 {
+			break;
+		}
+		auxv.push((a_type, a_val));
+	}
+	auxv
+}
+
+pub fn auxv_get(auxv: &[(u64, u64)], a_type: u64) -> Option<u64> {
+	auxv.iter().find(|(t, _)| *t == a_type).map(|(_, val)| *val)
+}
+
+//name and description of an entry, for 'info auxv'
+pub fn auxv_name(a_type: u64) -> (&'static str, &'static str) {
+	match a_type {
+		2 => ("AT_EXECFD", "File descriptor of program"),
+		3 => ("AT_PHDR", "Program headers for program"),
+		4 => ("AT_PHENT", "Size of program header entry"),
+		5 => ("AT_PHNUM", "Number of program headers"),
+		6 => ("AT_PAGESZ", "System page size"),
+		7 => ("AT_BASE", "Base address of interpreter"),
+		8 => ("AT_FLAGS", "Flags"),
+		9 => ("AT_ENTRY", "Entry point of program"),
+		11 => ("AT_UID", "Real user ID"),
+		12 => ("AT_EUID", "Effective user ID"),
+		13 => ("AT_GID", "Real group ID"),
+		14 => ("AT_EGID", "Effective group ID"),
+		15 => ("AT_PLATFORM", "String identifying platform"),
+		16 => ("AT_HWCAP", "Machine-dependent CPU capability hints"),
+		17 => ("AT_CLKTCK", "Frequency of times()"),
+		23 => ("AT_SECURE", "Boolean, was exec setuid-like?"),
+		24 => ("AT_BASE_PLATFORM", "String identifying base platform"),
+		25 => ("AT_RANDOM", "Address of 16 random bytes"),
+		26 => ("AT_HWCAP2", "Extension of AT_HWCAP"),
+		27 => ("AT_RSEQ_FEATURE_SIZE", "rseq supported feature size"),
+		28 => ("AT_RSEQ_ALIGN", "rseq allocation alignment"),
+		31 => ("AT_EXECFN", "File name of executable"),
+		33 => ("AT_SYSINFO_EHDR", "System-supplied DSO's ELF header"),
+		51 => ("AT_MINSIGSTKSZ", "Minimal stack size for signal delivery"),
+		_ => ("???", ""),
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn raw(entries: &[(u64, u64)]) -> Vec<u8> {
+		let mut data = Vec::new();
+		for (a_type, a_val) in entries {
+			data.extend_from_slice(&a_type.to_le_bytes());
+			data.extend_from_slice(&a_val.to_le_bytes());
+		}
+		data
+	}
+
+	#[test]
+	fn stops_at_at_null() {
+		let data = raw(&[(AT_PHDR, 0x40), (AT_ENTRY, 0x1040), (0, 0), (AT_BASE, 0x7000)]);
+		assert_eq!(parse_auxv(&data), vec![(AT_PHDR, 0x40), (AT_ENTRY, 0x1040)]);
+	}
+
+	#[test]
+	fn ignores_cut_off_entry() {
+		let mut data = raw(&[(AT_ENTRY, 0x1040)]);
+		data.extend_from_slice(&[7, 0, 0]);
+		assert_eq!(parse_auxv(&data), vec![(AT_ENTRY, 0x1040)]);
+	}
+
+	#[test]
+	fn lookup() {
+		let auxv = vec![(AT_ENTRY, 0x1040), (AT_BASE, 0)];
+		assert_eq!(auxv_get(&auxv, AT_ENTRY), Some(0x1040));
+		assert_eq!(auxv_get(&auxv, AT_BASE), Some(0));
+		assert_eq!(auxv_get(&auxv, AT_PHDR), None);
+	}
+
+	#[test]
+	fn own_process() {
+		//AT_PAGESZ is always there
+		let auxv = read_auxv(Pid::this());
+		assert_eq!(auxv_get(&auxv, 6), Some(4096));
+		assert!(auxv_get(&auxv, AT_ENTRY).is_some());
+	}
+}
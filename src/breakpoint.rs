@@ -1,8 +1,23 @@
+use std::collections::{BTreeMap, HashMap};
+
 use nix::errno::Errno;
 use nix::unistd::Pid;
 use nix::sys::ptrace;
 use core::ffi::c_void;
 
+use crate::dprintf::dprintf_fmt;
+
+//what happens to a breakpoint once its been hit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Disposition {
+	Keep,
+	//tbreak
+	Delete,
+	//b once, enable once
+	Disable,
+}
+
+//the int3 itself. There is only ever one at an address, however many breakpoints the user has put there
 #[derive(Clone)]
 #[derive(Copy)]
 #[derive(Debug)]
@@ -24,6 +39,10 @@ impl breakpoint {
 	}
 
 	pub fn enable(&mut self) -> Result<(), Errno> {
+		//int3 is already there. Reading it again would save it as the original byte
+		if self.enabled {
+			return Ok(());
+		}
 		let mut data = match ptrace::read(self.pid, self.addr as *mut c_void) {
 			Ok(mem_val) => mem_val as u64,
 			Err(err_num) => return Err(err_num),
@@ -40,6 +59,9 @@ impl breakpoint {
 	}
 
 	pub fn disable(&mut self) -> Result<(),Errno> {
+		if !self.enabled {
+			return Ok(());
+		}
 		let mut data = match ptrace::read(self.pid, self.addr as *mut c_void) {
 			Ok(mem_val) => mem_val as u64,
 			Err(err_num) => return Err(err_num),
@@ -52,91 +74,420 @@ impl breakpoint {
 		self.enabled = false;
 		Ok(())
 	}
+
+	//the byte the int3 replaced
+	pub fn saved_byte(&self) -> u8 {
+		self.saved_data
+	}
+}
+
+//a breakpoint as the user sees it. Several can share an address (and its int3)
+#[derive(Clone, Debug)]
+pub struct bp_entry {
+	pub id: usize,
+	pub addr: usize,
+	pub enabled: bool,
+	//times its been hit (with its condition true), this run
+	pub hits: u64,
+	//hits left to go straight past without stopping
+	pub ignore_count: u64,
+	pub disposition: Disposition,
+	//the process only stops at it when this is true
+	pub condition: Option<String>,
+	//debugger commands run every time it stops the process
+	pub commands: Vec<String>,
+	//dprintf. Prints this instead of stopping
+	pub dprintf: Option<dprintf_fmt>,
 }
 
-//currently unimplemented
-//How we store breakpoints. Was intiially a hashmap of addresses and breakpoints
-//But that made it inconvenient to reference breakpoints (have to type whole address to enable/disable/delete/do anything)
-//so, vector of breakpoints instead (can access via index
-//The second vector (addr_list) is to preent duplicate breakpoints (original reason for choosing hashmap)
+//the int3 at an address, and the ids of the breakpoints using it
+#[derive(Clone, Debug)]
+pub struct bp_site {
+	pub bp: breakpoint,
+	pub ids: Vec<usize>,
+	//the debugger wants to stop here itself (next, finish), whether or not any breakpoints do
+	pub internal: bool,
+}
+
+//rbreak. One id for a set of breakpoints, so they can be enabled, disabled and deleted together
+//each one in it is still a normal breakpoint with its own id
+#[derive(Clone, Debug)]
+pub struct bp_group {
+	pub id: usize,
+	//what made it (rbreak <regex> [module])
+	pub desc: String,
+	//id of each breakpoint in it, and the function its on
+	pub members: BTreeMap<usize, String>,
+}
+
+//How we store breakpoints
+//Breakpoints are referred to by id. Ids only go up and are never reused, so an id means the same breakpoint for the whole session
+//sites maps an address to its int3, so working out which breakpoint a thread stopped on (or has to step past) is a hash lookup
+//The int3 is in memory as long as any of the breakpoints at its address are enabled
 pub struct bp_storage {
-	pub bp_list: Vec<Option<breakpoint>>,
-	pub addr_list: Vec<usize>,
+	//by id. Ordered, so they list in the order they were made
+	pub bps: BTreeMap<usize, bp_entry>,
+	pub sites: HashMap<usize, bp_site>,
+	//share ids with the breakpoints
+	pub groups: BTreeMap<usize, bp_group>,
+	//id the next breakpoint gets
+	pub next_id: usize,
+	pid: Pid,
 }
 
 impl bp_storage {
-	pub fn New() -> bp_storage {
+	pub fn New(pid: Pid) -> bp_storage {
 		bp_storage {
-			bp_list: Vec::new(),
-			addr_list: Vec::new(),
+			bps: BTreeMap::new(),
+			sites: HashMap::new(),
+			groups: BTreeMap::new(),
+			next_id: 0,
+			pid,
 		}
 	}
 
+	//an id for something other than a breakpoint (watchpoints). Never given to anything else
+	pub fn new_id(&mut self) -> usize {
+		let id = self.next_id;
+		self.next_id += 1;
+		id
+	}
 
-	pub fn insert(&mut self, bp: breakpoint) -> Result<(),()> {
-		//breakpoint already set at that address
-		if self.addr_list.contains(&bp.addr) {
-			return Err(());
+	//new enabled breakpoint at addr. Returns its id
+	pub fn add(&mut self, addr: usize, disposition: Disposition) -> Result<usize, Errno> {
+		let id = self.next_id;
+		self.add_entry(id, addr, disposition);
+		if let Err(err_num) = self.sync(addr) {
+			self.delete(id).ok();
+			return Err(err_num);
 		}
-		self.bp_list.push(Some(bp));
-		self.addr_list.push(bp.addr);
-		Ok(())
+		Ok(id)
 	}
 
+	//a breakpoint brought over from the last run. Keeps the id it had
+	//starts off disabled, enable puts the int3 in
+	pub fn restore(&mut self, id: usize, addr: usize, disposition: Disposition) {
+		self.add_entry(id, addr, disposition);
+		self.bps.get_mut(&id).unwrap().enabled = false;
+	}
 
-	pub fn delete(&mut self, idx: usize) -> Result<(),()> {
-		if idx > (self.bp_list.len()-1) {
-			return Err(());
+	fn add_entry(&mut self, id: usize, addr: usize, disposition: Disposition) {
+		let pid = self.pid;
+		let site = self.sites.entry(addr).or_insert_with(|| bp_site { bp: breakpoint::New(pid, addr), ids: Vec::new(), internal: false });
+		site.ids.push(id);
+		self.bps.insert(id, bp_entry {
+			id,
+			addr,
+			//sync decides if the int3 goes in
+			enabled: true,
+			hits: 0,
+			ignore_count: 0,
+			disposition,
+			condition: None,
+			commands: Vec::new(),
+			dprintf: None,
+		});
+		self.next_id = self.next_id.max(id + 1);
+	}
+
+	//puts the int3 in or takes it out, depending on if anything at addr is enabled
+	fn sync(&mut self, addr: usize) -> Result<(), Errno> {
+		let site = match self.sites.get_mut(&addr) {
+			Some(site) => site,
+			None => {return Ok(());},
+		};
+		let bps = &self.bps;
+		if site.internal || site.ids.iter().any(|id| bps.get(id).is_some_and(|bp| bp.enabled)) {
+			site.bp.enable()
 		}
-		match self.bp_list[idx] {
-			None => {
-				return Err(());
-			},
-			Some(mut bp) => {
-				bp.disable();
-				self.bp_list[idx] = None;	
-				self.addr_list[idx] = 0;
-			},
+		else {
+			site.bp.disable()
+		}
+	}
+
+	//an empty group. Breakpoints are put in it with join
+	pub fn new_group(&mut self, desc: &str) -> usize {
+		let id = self.next_id;
+		self.restore_group(bp_group { id, desc: desc.to_string(), members: BTreeMap::new() });
+		id
+	}
+
+	//a group brought over from the last run. Its breakpoints are restored separately
+	pub fn restore_group(&mut self, group: bp_group) {
+		self.next_id = self.next_id.max(group.id + 1);
+		self.groups.insert(group.id, group);
+	}
+
+	pub fn join(&mut self, group: usize, id: usize, func: &str) {
+		if let Some(group) = self.groups.get_mut(&group) {
+			group.members.insert(id, func.to_string());
 		}
+	}
+
+	//group a breakpoint is in
+	pub fn group_of(&self, id: usize) -> Option<&bp_group> {
+		self.groups.values().find(|group| group.members.contains_key(&id))
+	}
+
+	//the breakpoints an id refers to. Everything in it for a group
+	pub fn ids_in(&self, id: usize) -> Vec<usize> {
+		match self.groups.get(&id) {
+			Some(group) => group.members.keys().cloned().collect(),
+			None if self.bps.contains_key(&id) => vec![id],
+			None => Vec::new(),
+		}
+	}
+
+	//deleting a group deletes everything in it
+	pub fn delete(&mut self, id: usize) -> Result<(),()> {
+		if let Some(group) = self.groups.remove(&id) {
+			for member in group.members.keys() {
+				self.delete(*member).ok();
+			}
+			return Ok(());
+		}
+		let bp = match self.bps.remove(&id) {
+			Some(bp) => bp,
+			None => {return Err(());},
+		};
+		//group goes once theres nothing left in it
+		self.groups.values_mut().for_each(|group| {group.members.remove(&id);});
+		self.groups.retain(|_, group| !group.members.is_empty());
+		if let Some(site) = self.sites.get_mut(&bp.addr) {
+			site.ids.retain(|other| *other != id);
+			//last one there. Original byte goes back
+			if site.ids.is_empty() && !site.internal {
+				site.bp.disable().ok();
+				self.sites.remove(&bp.addr);
+				return Ok(());
+			}
+		}
+		self.sync(bp.addr).ok();
 		Ok(())
 	}
 
-	pub fn enable(&mut self, idx: usize) -> Result<(),()> {
-		if idx > (self.bp_list.len()-1) {
-			return Err(());
+	//enabling a group fails if any of it cant be put in
+	pub fn enable(&mut self, id: usize) -> Result<(),()> {
+		if let Some(group) = self.groups.get(&id) {
+			let members : Vec<usize> = group.members.keys().cloned().collect();
+			let failed = members.iter().filter(|member| self.enable(**member).is_err()).count();
+			return if failed == 0 {Ok(())} else {Err(())};
 		}
-		match self.bp_list[idx] {
-			None => {
-				return Err(());
-			},
-			Some(mut bp) => {
-				bp.enable();
-				self.bp_list[idx] = Some(bp);
+		let addr = match self.bps.get_mut(&id) {
+			Some(bp) => {
+				bp.enabled = true;
+				bp.addr
 			},
+			None => {return Err(());},
+		};
+		if self.sync(addr).is_err() {
+			self.bps.get_mut(&id).unwrap().enabled = false;
+			return Err(());
 		}
 		Ok(())
 	}
 
-	pub fn disable(&mut self, idx: usize) -> Result<(),()> {
-		if idx > (self.bp_list.len()-1) {
-			return Err(());
+	pub fn disable(&mut self, id: usize) -> Result<(),()> {
+		if let Some(group) = self.groups.get(&id) {
+			for member in group.members.keys().cloned().collect::<Vec<usize>>() {
+				self.disable(member).ok();
+			}
+			return Ok(());
+		}
+		let addr = match self.bps.get_mut(&id) {
+			Some(bp) => {
+				bp.enabled = false;
+				bp.addr
+			},
+			None => {return Err(());},
+		};
+		self.sync(addr).ok();
+		Ok(())
+	}
+
+	//copy of the breakpoints for a forked child. Its memory is a copy of the parents, int3's and all
+	pub fn clone_for(&self, pid: Pid) -> bp_storage {
+		let mut sites = self.sites.clone();
+		for site in sites.values_mut() {
+			site.bp.pid = pid;
+		}
+		bp_storage {
+			bps: self.bps.clone(),
+			sites,
+			groups: self.groups.clone(),
+			next_id: self.next_id,
+			pid,
+		}
+	}
+
+	//puts every int3 that should be there back into memory
+	//only for when the memory is known not to have them in it (once a vfork child is gone)
+	//returns the ids of those that couldnt be put back (address no longer mapped). They are disabled
+	pub fn reinsert(&mut self) -> Vec<usize> {
+		let mut failed = Vec::new();
+		for site in self.sites.values_mut() {
+			if !site.bp.enabled {
+				continue;
+			}
+			//memory doesnt have the int3 in it any more, so it has to be put in again
+			site.bp.enabled = false;
+			if site.bp.enable().is_err() {
+				for id in &site.ids {
+					if let Some(bp) = self.bps.get_mut(id) {
+						if bp.enabled {
+							bp.enabled = false;
+							failed.push(*id);
+						}
+					}
+				}
+			}
+		}
+		failed.sort();
+		failed
+	}
+
+	//after an exec. The int3s went away with the old image, so nothing is written back
+	//moved gives where a breakpoint is in the new image, None if it isnt in it. Those stay where they were, disabled
+	//returns the ids of enabled breakpoints that couldnt be put into the new image. They are disabled
+	pub fn relocate<F: Fn(usize) -> Option<usize>>(&mut self, moved: F) -> Vec<usize> {
+		let pid = self.pid;
+		let mut failed = Vec::new();
+		//internal ones belonged to whatever the old image was doing
+		self.sites.clear();
+		for bp in self.bps.values_mut() {
+			match moved(bp.addr) {
+				Some(addr) => {bp.addr = addr;},
+				None if bp.enabled => {
+					bp.enabled = false;
+					failed.push(bp.id);
+				},
+				None => {},
+			};
+			let site = self.sites.entry(bp.addr).or_insert_with(|| bp_site { bp: breakpoint::New(pid, bp.addr), ids: Vec::new(), internal: false });
+			site.ids.push(bp.id);
+		}
+		let addrs : Vec<usize> = self.sites.keys().cloned().collect();
+		for addr in addrs {
+			if self.sync(addr).is_ok() {
+				continue;
+			}
+			for id in &self.sites[&addr].ids {
+				let bp = self.bps.get_mut(id).unwrap();
+				if bp.enabled {
+					bp.enabled = false;
+					failed.push(*id);
+				}
+			}
+		}
+		failed.sort();
+		failed
+	}
+
+	//restores the original bytes of every breakpoint and forgets them all
+	//needed before detaching, otherwise the process is left with int3's in it
+	pub fn remove_all(&mut self) {
+		for site in self.sites.values_mut() {
+			site.bp.disable().ok();
+		}
+		self.sites.clear();
+		self.bps.clear();
+		self.groups.clear();
+	}
+
+	//an int3 that isnt a breakpoint, for the debugger to stop at. Doesnt show up anywhere
+	//shares the address with any breakpoints already there
+	pub fn add_internal(&mut self, addr: usize) -> Result<(), Errno> {
+		let pid = self.pid;
+		let site = self.sites.entry(addr).or_insert_with(|| bp_site { bp: breakpoint::New(pid, addr), ids: Vec::new(), internal: false });
+		site.internal = true;
+		let res = self.sync(addr);
+		if res.is_err() {
+			self.remove_internal(addr);
+		}
+		res
+	}
+
+	pub fn remove_internal(&mut self, addr: usize) {
+		let site = match self.sites.get_mut(&addr) {
+			Some(site) => site,
+			None => {return;},
+		};
+		site.internal = false;
+		if site.ids.is_empty() {
+			site.bp.disable().ok();
+			self.sites.remove(&addr);
+			return;
+		}
+		self.sync(addr).ok();
+	}
+
+	//takes the int3 at addr out for a moment, so the instruction under it can be stepped
+	//returns false if there wasnt one in. resume puts it back
+	pub fn suspend(&mut self, addr: usize) -> bool {
+		match self.sites.get_mut(&addr) {
+			Some(site) if site.bp.enabled => site.bp.disable().is_ok(),
+			_ => false,
+		}
+	}
+
+	pub fn resume(&mut self, addr: usize) {
+		if let Some(site) = self.sites.get_mut(&addr) {
+			site.bp.enable().ok();
+		}
+	}
+
+	//None makes it unconditional again. For a group, every breakpoint in it gets the condition
+	pub fn set_condition(&mut self, id: usize, condition: Option<String>) -> Result<(),()> {
+		if let Some(group) = self.groups.get(&id) {
+			for member in group.members.keys().cloned().collect::<Vec<usize>>() {
+				self.set_condition(member, condition.clone()).ok();
+			}
+			return Ok(());
 		}
-		match self.bp_list[idx] {
-			None => {
-				return Err(());
+		match self.bps.get_mut(&id) {
+			Some(bp) => {
+				bp.condition = condition;
+				Ok(())
 			},
-			Some(mut bp) => {
-				bp.disable();
-				self.bp_list[idx] = Some(bp);
+			None => Err(()),
+		}
+	}
+
+	//an empty list takes them away
+	pub fn set_commands(&mut self, id: usize, commands: Vec<String>) -> Result<(),()> {
+		if let Some(group) = self.groups.get(&id) {
+			for member in group.members.keys().cloned().collect::<Vec<usize>>() {
+				self.set_commands(member, commands.clone()).ok();
+			}
+			return Ok(());
+		}
+		match self.bps.get_mut(&id) {
+			Some(bp) => {
+				bp.commands = commands;
+				Ok(())
 			},
+			None => Err(()),
 		}
-		Ok(())
 	}
 
-	pub fn contains(&self, idx: usize) -> bool {
-		if self.addr_list.contains(&idx) {
-			return true;
+	pub fn get(&self, id: usize) -> Option<&bp_entry> {
+		self.bps.get(&id)
+	}
+
+	pub fn get_mut(&mut self, id: usize) -> Option<&mut bp_entry> {
+		self.bps.get_mut(&id)
+	}
+
+	//ids of every breakpoint at addr, enabled or not
+	pub fn ids_at(&self, addr: usize) -> &[usize] {
+		match self.sites.get(&addr) {
+			Some(site) => &site.ids,
+			None => &[],
 		}
-		false
+	}
+
+	//whether our int3 is in memory at addr
+	pub fn inserted(&self, addr: usize) -> bool {
+		self.sites.get(&addr).is_some_and(|site| site.bp.enabled)
 	}
 }
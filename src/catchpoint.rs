@@ -0,0 +1,91 @@
+use nix::sys::signal::Signal;
+
+use crate::signals::parse_signal;
+use crate::syscalls::{syscall_name, syscall_number};
+
+
+//events that stop the process, rather than an address being reached
+#[derive(Clone, Debug, PartialEq)]
+pub enum CatchKind {
+	//syscall numbers. Empty catches every syscall. Stops on the way in and on the way out
+	Syscall(Vec<u64>),
+	//empty catches every signal, except the ones the debugger uses itself
+	//stops even if handle has been told not to
+	Signal(Vec<Signal>),
+	//fork and vfork
+	Fork,
+	Exec,
+	//the process is about to exit. Registers and memory can still be looked at
+	Exit,
+}
+
+impl CatchKind {
+	pub fn describe(&self) -> String {
+		match self {
+			CatchKind::Syscall(nrs) if nrs.is_empty() => String::from("syscall \"<any syscall>\""),
+			CatchKind::Syscall(nrs) => {
+				let names : Vec<String> = nrs.iter().map(|nr| format!("{} [{}]", syscall_name(*nr), nr)).collect();
+				format!("syscall \"{}\"", names.join(" "))
+			},
+			CatchKind::Signal(sigs) if sigs.is_empty() => String::from("signal <standard signals>"),
+			CatchKind::Signal(sigs) => {
+				let names : Vec<&str> = sigs.iter().map(|sig| sig.as_str()).collect();
+				format!("signal {}", names.join(" "))
+			},
+			CatchKind::Fork => String::from("fork"),
+			CatchKind::Exec => String::from("exec"),
+			CatchKind::Exit => String::from("exit"),
+		}
+	}
+
+	pub fn catches_syscall(&self, nr: u64) -> bool {
+		match self {
+			CatchKind::Syscall(nrs) => nrs.is_empty() || nrs.contains(&nr),
+			_ => false,
+		}
+	}
+
+	pub fn catches_signal(&self, sig: Signal) -> bool {
+		match self {
+			CatchKind::Signal(sigs) if sigs.is_empty() => sig != Signal::SIGTRAP && sig != Signal::SIGINT,
+			CatchKind::Signal(sigs) => sigs.contains(&sig),
+			_ => false,
+		}
+	}
+}
+
+//catch syscall [name|nr]...
+//catch signal [SIG]...
+//catch fork | exec | exit
+pub fn parse_catch(args: &[&str]) -> Result<CatchKind, String> {
+	let what = match args.first() {
+		Some(what) => *what,
+		None => {return Err(String::from("Catch command needs an event: syscall, signal, fork, exec or exit"));},
+	};
+	match what {
+		"syscall" => {
+			let mut nrs = Vec::new();
+			for name in &args[1..] {
+				match syscall_number(name) {
+					Some(nr) => {nrs.push(nr);},
+					None => {return Err(format!("Unknown syscall {}", name));},
+				};
+			}
+			Ok(CatchKind::Syscall(nrs))
+		},
+		"signal" => {
+			let mut sigs = Vec::new();
+			for name in &args[1..] {
+				match parse_signal(name) {
+					Some(sig) => {sigs.push(sig);},
+					None => {return Err(format!("Unknown signal {}", name));},
+				};
+			}
+			Ok(CatchKind::Signal(sigs))
+		},
+		"fork" | "vfork" => Ok(CatchKind::Fork),
+		"exec" => Ok(CatchKind::Exec),
+		"exit" => Ok(CatchKind::Exit),
+		_ => Err(format!("Unknown event {}. Can catch syscall, signal, fork, exec or exit", what)),
+	}
+}
@@ -0,0 +1,419 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::convert::TryInto;
+
+use nix::unistd::Pid;
+use libc::user_regs_struct;
+
+use crate::debugger::Debugger;
+use crate::auxv::read_auxv;
+use crate::thread::thread_info;
+
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_FPREGSET: u32 = 2;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x46494c45;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PAGE_SIZE: u64 = 0x1000;
+//e_phnum this big means the real count is in a section header, which we dont write
+const PN_XNUM: usize = 0xffff;
+//memory is copied through a buffer this big, so huge mappings dont have to fit in memory all at once
+const CHUNK_SIZE: u64 = 0x100000;
+//sizeof(struct user_fpregs_struct) - what PTRACE_GETFPREGS fills in
+const FPREGS_SIZE: usize = 512;
+
+
+//one line of /proc/<pid>/maps
+struct mapping {
+	start: u64,
+	end: u64,
+	flags: u32,
+	offset: u64,
+	path: Option<String>,
+}
+
+fn read_maps(pid: Pid) -> Result<Vec<mapping>, String> {
+	let maps = match fs::read_to_string(format!("/proc/{}/maps", pid)) {
+		Ok(maps) => maps,
+		Err(err) => {return Err(format!("Failed to read memory maps of {}. Error was {:?}", pid, err.kind()));},
+	};
+	let mut all = Vec::new();
+	for line in maps.lines() {
+		//start-end perms offset dev inode [path]
+		let fields : Vec<&str> = line.split_whitespace().collect();
+		if fields.len() < 5 {
+			continue;
+		}
+		let range : Vec<u64> = fields[0].split('-').filter_map(|x| u64::from_str_radix(x, 16).ok()).collect();
+		if range.len() != 2 {
+			continue;
+		}
+		let perms = fields[1].as_bytes();
+		//unreadable mappings (guard pages) arent dumped. vsyscall cant be read through /proc/<pid>/mem
+		if perms[0] != b'r' || line.ends_with("[vsyscall]") {
+			continue;
+		}
+		let mut flags = PF_R;
+		if perms[1] == b'w' {
+			flags |= PF_W;
+		}
+		if perms[2] == b'x' {
+			flags |= PF_X;
+		}
+		all.push(mapping {
+			start: range[0],
+			end: range[1],
+			flags,
+			offset: u64::from_str_radix(fields[2], 16).unwrap_or(0),
+			//only real files. Not [heap], [stack] etc
+			path: fields.get(5).filter(|path| path.starts_with('/')).map(|path| path.to_string()),
+		});
+	}
+	Ok(all)
+}
+
+
+//note header is namesz, descsz, type. Name and desc are each padded to 4 bytes
+fn push_note(buf: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+	let name = b"CORE\0";
+	buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+	buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+	buf.extend_from_slice(&note_type.to_le_bytes());
+	buf.extend_from_slice(name);
+	pad_to(buf, 4);
+	buf.extend_from_slice(desc);
+	pad_to(buf, 4);
+}
+
+fn pad_to(buf: &mut Vec<u8>, align: usize) {
+	while !buf.len().is_multiple_of(align) {
+		buf.push(0);
+	}
+}
+
+//same order as the kernels user_regs_struct (and libc's)
+fn regs_bytes(regs: &user_regs_struct) -> Vec<u8> {
+	let vals = [regs.r15, regs.r14, regs.r13, regs.r12, regs.rbp, regs.rbx, regs.r11, regs.r10,
+		regs.r9, regs.r8, regs.rax, regs.rcx, regs.rdx, regs.rsi, regs.rdi, regs.orig_rax,
+		regs.rip, regs.cs, regs.eflags, regs.rsp, regs.ss, regs.fs_base, regs.gs_base,
+		regs.ds, regs.es, regs.fs, regs.gs];
+	let mut buf = Vec::new();
+	for val in vals.iter() {
+		buf.extend_from_slice(&val.to_le_bytes());
+	}
+	buf
+}
+
+pub fn regs_from_bytes(buf: &[u8]) -> user_regs_struct {
+	let val = |idx: usize| u64::from_le_bytes(buf[idx*8..idx*8+8].try_into().unwrap());
+	user_regs_struct {
+		r15: val(0), r14: val(1), r13: val(2), r12: val(3), rbp: val(4), rbx: val(5), r11: val(6), r10: val(7),
+		r9: val(8), r8: val(9), rax: val(10), rcx: val(11), rdx: val(12), rsi: val(13), rdi: val(14), orig_rax: val(15),
+		rip: val(16), cs: val(17), eflags: val(18), rsp: val(19), ss: val(20), fs_base: val(21), gs_base: val(22),
+		ds: val(23), es: val(24), fs: val(25), gs: val(26),
+	}
+}
+
+//struct elf_prstatus. 336 bytes on x86_64
+fn prstatus(pid: Pid, thread: &thread_info, regs: &user_regs_struct, fpvalid: bool) -> Vec<u8> {
+	let sig = thread.stop_sig.map_or(0, |sig| sig as i32);
+	let mut buf = Vec::new();
+	//pr_info (signo, code, errno), pr_cursig + padding
+	buf.extend_from_slice(&sig.to_le_bytes());
+	buf.extend_from_slice(&0i32.to_le_bytes());
+	buf.extend_from_slice(&0i32.to_le_bytes());
+	buf.extend_from_slice(&(sig as i16).to_le_bytes());
+	buf.extend_from_slice(&[0; 2]);
+	//pr_sigpend, pr_sighold
+	buf.extend_from_slice(&[0; 16]);
+	//pr_pid (the thread), pr_ppid, pr_pgrp, pr_sid
+	buf.extend_from_slice(&thread.tid.as_raw().to_le_bytes());
+	buf.extend_from_slice(&0i32.to_le_bytes());
+	buf.extend_from_slice(&pid.as_raw().to_le_bytes());
+	buf.extend_from_slice(&0i32.to_le_bytes());
+	//pr_utime, pr_stime, pr_cutime, pr_cstime
+	buf.extend_from_slice(&[0; 64]);
+	buf.extend_from_slice(&regs_bytes(regs));
+	buf.extend_from_slice(&(fpvalid as i32).to_le_bytes());
+	buf.extend_from_slice(&[0; 4]);
+	buf
+}
+
+//struct elf_prpsinfo. 136 bytes on x86_64. Tools show the program name from here
+fn prpsinfo(pid: Pid) -> Vec<u8> {
+	let mut buf = Vec::new();
+	//pr_state, pr_sname ('t' for traced), pr_zomb, pr_nice + padding, pr_flag
+	buf.extend_from_slice(&[0, b't', 0, 0, 0, 0, 0, 0]);
+	buf.extend_from_slice(&[0; 8]);
+	//pr_uid, pr_gid
+	buf.extend_from_slice(&(unsafe { libc::getuid() }).to_le_bytes());
+	buf.extend_from_slice(&(unsafe { libc::getgid() }).to_le_bytes());
+	//pr_pid, pr_ppid, pr_pgrp, pr_sid
+	buf.extend_from_slice(&pid.as_raw().to_le_bytes());
+	buf.extend_from_slice(&[0; 12]);
+
+	let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+	let mut fname = [0u8; 16];
+	for (i, byte) in comm.trim_end().bytes().take(15).enumerate() {
+		fname[i] = byte;
+	}
+	buf.extend_from_slice(&fname);
+
+	//cmdline args are seperated by nulls. psargs uses spaces
+	let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+	let mut psargs = [0u8; 80];
+	for (i, byte) in cmdline.iter().take(79).enumerate() {
+		psargs[i] = if *byte == 0 {b' '} else {*byte};
+	}
+	buf.extend_from_slice(&psargs);
+	buf
+}
+
+//struct user_fpregs_struct, straight from ptrace
+fn fpregs(tid: Pid) -> Option<Vec<u8>> {
+	let mut buf = vec![0u8; FPREGS_SIZE];
+	let ret = unsafe { libc::ptrace(libc::PTRACE_GETFPREGS, tid.as_raw(), 0, buf.as_mut_ptr()) };
+	if ret < 0 {
+		return None;
+	}
+	Some(buf)
+}
+
+//count, page size, then (start, end, offset in pages) for each file mapping, then all their paths
+fn file_note(maps: &[mapping]) -> Vec<u8> {
+	let files : Vec<&mapping> = maps.iter().filter(|map| map.path.is_some()).collect();
+	let mut buf = Vec::new();
+	buf.extend_from_slice(&(files.len() as u64).to_le_bytes());
+	buf.extend_from_slice(&PAGE_SIZE.to_le_bytes());
+	for map in &files {
+		buf.extend_from_slice(&map.start.to_le_bytes());
+		buf.extend_from_slice(&map.end.to_le_bytes());
+		buf.extend_from_slice(&(map.offset / PAGE_SIZE).to_le_bytes());
+	}
+	for map in &files {
+		buf.extend_from_slice(map.path.as_ref().unwrap().as_bytes());
+		buf.push(0);
+	}
+	buf
+}
+
+fn auxv_note(auxv: &[(u64, u64)]) -> Vec<u8> {
+	let mut buf = Vec::new();
+	for (a_type, a_val) in auxv {
+		buf.extend_from_slice(&a_type.to_le_bytes());
+		buf.extend_from_slice(&a_val.to_le_bytes());
+	}
+	//AT_NULL
+	buf.extend_from_slice(&[0; 16]);
+	buf
+}
+
+fn phdr(p_type: u32, flags: u32, offset: u64, vaddr: u64, filesz: u64, memsz: u64, align: u64) -> Vec<u8> {
+	let mut buf = Vec::with_capacity(PHDR_SIZE);
+	buf.extend_from_slice(&p_type.to_le_bytes());
+	buf.extend_from_slice(&flags.to_le_bytes());
+	buf.extend_from_slice(&offset.to_le_bytes());
+	buf.extend_from_slice(&vaddr.to_le_bytes());
+	//p_paddr
+	buf.extend_from_slice(&0u64.to_le_bytes());
+	buf.extend_from_slice(&filesz.to_le_bytes());
+	buf.extend_from_slice(&memsz.to_le_bytes());
+	buf.extend_from_slice(&align.to_le_bytes());
+	buf
+}
+
+
+//writes the current process out as an ELF core file. Returns how many segments were written
+//Process must be stopped
+pub fn write_core(dbg: &Debugger, path: &str) -> Result<usize, String> {
+	let pid = dbg.m_pid;
+	let maps = read_maps(pid)?;
+	if maps.len() + 1 >= PN_XNUM {
+		return Err(format!("Process {} has {} mappings, too many for a core file", pid, maps.len()));
+	}
+
+	//notes. Selected thread goes first, as thats the one tools treat as current
+	let mut notes = Vec::new();
+	push_note(&mut notes, NT_PRPSINFO, &prpsinfo(pid));
+	let mut threads : Vec<&thread_info> = dbg.threads.threads.values().collect();
+	threads.sort_by_key(|thread| thread.tid != dbg.cur_tid());
+	for thread in threads {
+		let regs = match thread.regs {
+			Some(regs) => regs,
+			None => {continue;},
+		};
+		let fp = fpregs(thread.tid);
+		push_note(&mut notes, NT_PRSTATUS, &prstatus(pid, thread, &regs, fp.is_some()));
+		if let Some(fp) = fp {
+			push_note(&mut notes, NT_FPREGSET, &fp);
+		}
+	}
+	push_note(&mut notes, NT_AUXV, &auxv_note(&read_auxv(pid)));
+	push_note(&mut notes, NT_FILE, &file_note(&maps));
+	let header = core_header(&maps, &notes);
+
+	let mut file = match File::create(path) {
+		Ok(file) => file,
+		Err(err) => {return Err(format!("Failed to create {}. Error was {:?}", path, err.kind()));},
+	};
+	let mem = match File::open(format!("/proc/{}/mem", pid)) {
+		Ok(mem) => mem,
+		Err(err) => {return Err(format!("Failed to open memory of {}. Error was {:?}", pid, err.kind()));},
+	};
+	if let Err(err) = file.write_all(&header) {
+		return Err(format!("Failed to write {}. Error was {:?}", path, err.kind()));
+	}
+
+	let mut buf = vec![0u8; CHUNK_SIZE as usize];
+	for map in &maps {
+		let mut start = map.start;
+		while start < map.end {
+			let data = &mut buf[..(map.end - start).min(CHUNK_SIZE) as usize];
+			let end = start + data.len() as u64;
+			//some special mappings (e.g [vvar]) cant be read. Leave them zeroed rather than giving up
+			if mem.read_exact_at(data, start).is_err() {
+				data.iter_mut().for_each(|byte| *byte = 0);
+			}
+			//the dump should have the original code, not our int3's
+			for site in dbg.bp_table.sites.values() {
+				let bp = &site.bp;
+				if bp.enabled && start <= bp.addr as u64 && (bp.addr as u64) < end {
+					data[(bp.addr as u64 - start) as usize] = bp.saved_byte();
+				}
+			}
+			if let Err(err) = file.write_all(data) {
+				return Err(format!("Failed to write {}. Error was {:?}", path, err.kind()));
+			}
+			start = end;
+		}
+	}
+	Ok(maps.len())
+}
+
+//ELF header, program headers and notes. The contents of each mapping follow it, in order, with nothing in between
+fn core_header(maps: &[mapping], notes: &[u8]) -> Vec<u8> {
+	let phnum = maps.len() + 1;
+	let notes_off = EHDR_SIZE + phnum*PHDR_SIZE;
+	let data_off = (notes_off + notes.len() + PAGE_SIZE as usize - 1) & !(PAGE_SIZE as usize - 1);
+
+	let mut header = Vec::new();
+	//e_ident: magic, ELFCLASS64, ELFDATA2LSB, EV_CURRENT, ELFOSABI_NONE
+	header.extend_from_slice(b"\x7fELF\x02\x01\x01\x00");
+	header.extend_from_slice(&[0; 8]);
+	//e_type ET_CORE, e_machine EM_X86_64, e_version
+	header.extend_from_slice(&4u16.to_le_bytes());
+	header.extend_from_slice(&62u16.to_le_bytes());
+	header.extend_from_slice(&1u32.to_le_bytes());
+	//e_entry, e_phoff, e_shoff, e_flags
+	header.extend_from_slice(&0u64.to_le_bytes());
+	header.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+	header.extend_from_slice(&0u64.to_le_bytes());
+	header.extend_from_slice(&0u32.to_le_bytes());
+	//e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+	header.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+	header.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+	header.extend_from_slice(&(phnum as u16).to_le_bytes());
+	header.extend_from_slice(&[0; 6]);
+
+	header.extend_from_slice(&phdr(PT_NOTE, 0, notes_off as u64, 0, notes.len() as u64, 0, 0));
+	let mut offset = data_off as u64;
+	for map in maps {
+		let size = map.end - map.start;
+		header.extend_from_slice(&phdr(PT_LOAD, map.flags, offset, map.start, size, size, PAGE_SIZE));
+		offset += size;
+	}
+	header.extend_from_slice(notes);
+	header.resize(data_off, 0);
+	header
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use std::process;
+	use nix::sys::signal::Signal;
+
+	use crate::core_file::CoreFile;
+	use crate::thread::ThreadState;
+
+	fn regs_with(seed: u64) -> user_regs_struct {
+		let vals : Vec<u8> = (0..27u64).flat_map(|idx| (seed + idx).to_le_bytes().to_vec()).collect();
+		regs_from_bytes(&vals)
+	}
+
+	#[test]
+	fn regs_round_trip() {
+		let regs = regs_with(0x100);
+		let bytes = regs_bytes(&regs);
+		assert_eq!(bytes.len(), 27*8);
+		let back = regs_from_bytes(&bytes);
+		assert_eq!(back.rip, regs.rip);
+		assert_eq!(back.rsp, regs.rsp);
+		assert_eq!(bytes, regs_bytes(&back));
+	}
+
+	//writes a core the same way write_core does (made up threads and memory, rather than a real process) and reads it back
+	#[test]
+	fn core_round_trip() {
+		let pid = Pid::this();
+		let maps = vec![
+			mapping { start: 0x400000, end: 0x401000, flags: PF_R | PF_X, offset: 0x2000, path: Some(String::from("/bin/prog")) },
+			mapping { start: 0x600000, end: 0x602000, flags: PF_R | PF_W, offset: 0, path: None },
+		];
+		let main = thread_info { tid: Pid::from_raw(100), num: 1, state: ThreadState::Stopped, regs: None, pending_sig: None, stop_sig: Some(Signal::SIGSEGV) };
+		let other = thread_info { tid: Pid::from_raw(101), num: 2, state: ThreadState::Stopped, regs: None, pending_sig: None, stop_sig: None };
+
+		let mut notes = Vec::new();
+		push_note(&mut notes, NT_PRPSINFO, &prpsinfo(pid));
+		push_note(&mut notes, NT_PRSTATUS, &prstatus(pid, &main, &regs_with(0x1000), false));
+		push_note(&mut notes, NT_PRSTATUS, &prstatus(pid, &other, &regs_with(0x2000), false));
+		push_note(&mut notes, NT_AUXV, &auxv_note(&[(9, 0x400100), (6, 0x1000)]));
+		push_note(&mut notes, NT_FILE, &file_note(&maps));
+
+		let mut core = core_header(&maps, &notes);
+		assert_eq!(core.len() % PAGE_SIZE as usize, 0);
+		for map in &maps {
+			let len = (map.end - map.start) as usize;
+			core.extend((0..len).map(|idx| (map.start as usize + idx) as u8));
+		}
+		let path = env::temp_dir().join(format!("core_round_trip.{}", process::id()));
+		fs::write(&path, &core).unwrap();
+		let read = CoreFile::New(path.to_str().unwrap());
+		fs::remove_file(&path).unwrap();
+		let read = read.unwrap();
+
+		assert_eq!(read.pid, pid);
+		assert_eq!(read.threads.len(), 2);
+		assert_eq!(read.threads[0].tid, Pid::from_raw(100));
+		assert_eq!(read.threads[0].sig, Some(Signal::SIGSEGV));
+		assert_eq!(regs_bytes(&read.threads[0].regs), regs_bytes(&regs_with(0x1000)));
+		assert_eq!(read.threads[1].tid, Pid::from_raw(101));
+		assert_eq!(read.threads[1].sig, None);
+		assert_eq!(read.threads[1].regs.rip, regs_with(0x2000).rip);
+		assert_eq!(read.auxv, vec![(9, 0x400100), (6, 0x1000)]);
+
+		//only file backed mappings are in NT_FILE. Offsets go through it in pages and come back in bytes
+		assert_eq!(read.files.len(), 1);
+		assert_eq!(read.files[0].path, "/bin/prog");
+		assert_eq!((read.files[0].start, read.files[0].end, read.files[0].offset), (0x400000, 0x401000, 0x2000));
+
+		//memory comes out of the segments, including across the end of one
+		assert_eq!(read.read_mem(0x400000), Ok(u64::from_le_bytes([0, 1, 2, 3, 4, 5, 6, 7])));
+		assert_eq!(read.read_mem(0x601ff8), Ok(u64::from_le_bytes([0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff])));
+		assert!(read.read_mem(0x601ffc).is_err());
+		assert!(read.read_mem(0x500000).is_err());
+	}
+}
@@ -0,0 +1,345 @@
+use std::fs;
+use std::convert::TryInto;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use nix::errno::Errno;
+use nix::unistd::Pid;
+use nix::sys::signal::Signal;
+use std::convert::TryFrom;
+use libc::user_regs_struct;
+
+use crate::core_dump::regs_from_bytes;
+
+
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x46494c45;
+
+//where pr_reg starts in struct elf_prstatus, and how big it is
+const PRSTATUS_REGS_OFF: usize = 112;
+const REGS_SIZE: usize = 27*8;
+
+
+//a PT_LOAD segment. Memory past filesz wasnt dumped (e.g unmodified code pages)
+struct segment {
+	vaddr: u64,
+	memsz: u64,
+	offset: u64,
+	filesz: u64,
+}
+
+//a thread as it was when the core was dumped
+pub struct core_thread {
+	pub tid: Pid,
+	pub sig: Option<Signal>,
+	pub regs: user_regs_struct,
+}
+
+//file backed mapping, from NT_FILE
+pub struct file_mapping {
+	pub start: u64,
+	pub end: u64,
+	pub offset: u64,
+	pub path: String,
+}
+
+//An ELF core file, read in full
+pub struct CoreFile {
+	pub path: String,
+	pub pid: Pid,
+	pub threads: Vec<core_thread>,
+	pub auxv: Vec<(u64, u64)>,
+	pub files: Vec<file_mapping>,
+	segments: Vec<segment>,
+	data: Vec<u8>,
+	//contents of mapped files, read the first time theyre needed. None if it couldnt be read
+	file_cache: RefCell<HashMap<String, Option<Vec<u8>>>>,
+}
+
+fn read_u16(data: &[u8], off: usize) -> Option<u16> {
+	Some(u16::from_le_bytes(data.get(off..off+2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], off: usize) -> Option<u32> {
+	Some(u32::from_le_bytes(data.get(off..off+4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], off: usize) -> Option<u64> {
+	Some(u64::from_le_bytes(data.get(off..off+8)?.try_into().ok()?))
+}
+
+impl CoreFile {
+	pub fn New(path: &str) -> Result<CoreFile, String> {
+		let data = match fs::read(path) {
+			Ok(data) => data,
+			Err(err) => {return Err(format!("Failed to read {}. Error was {:?}", path, err.kind()));},
+		};
+		let mut core = CoreFile {
+			path: path.to_string(),
+			pid: Pid::from_raw(0),
+			threads: Vec::new(),
+			auxv: Vec::new(),
+			files: Vec::new(),
+			segments: Vec::new(),
+			data: Vec::new(),
+			file_cache: RefCell::new(HashMap::new()),
+		};
+		if core.parse(&data).is_none() {
+			return Err(format!("{} is not a valid x86_64 ELF core file", path));
+		}
+		if core.threads.is_empty() {
+			return Err(format!("{} has no threads in it", path));
+		}
+		//no prpsinfo. First thread is the main one
+		if core.pid.as_raw() == 0 {
+			if let Some(thread) = core.threads.first() {
+				core.pid = thread.tid;
+			}
+		}
+		core.data = data;
+		Ok(core)
+	}
+
+	fn parse(&mut self, data: &[u8]) -> Option<()> {
+		if data.get(0..6)? != b"\x7fELF\x02\x01" || read_u16(data, 16)? != ET_CORE {
+			return None;
+		}
+		let e_phoff = read_u64(data, 32)? as usize;
+		let e_phentsize = read_u16(data, 54)? as usize;
+		let e_phnum = read_u16(data, 56)? as usize;
+
+		//anything read from the file can be garbage, so sizes and offsets from it are added up checked
+		for i in 0..e_phnum {
+			let phdr = e_phoff.checked_add(i*e_phentsize)?;
+			let p_type = read_u32(data, phdr)?;
+			let offset = read_u64(data, phdr + 8)?;
+			let vaddr = read_u64(data, phdr + 16)?;
+			let filesz = read_u64(data, phdr + 32)?;
+			let memsz = read_u64(data, phdr + 40)?;
+			match p_type {
+				PT_LOAD => {
+					self.segments.push(segment { vaddr, memsz, offset, filesz });
+				},
+				PT_NOTE => {
+					self.parse_notes(data.get(offset as usize..offset.checked_add(filesz)? as usize)?)?;
+				},
+				_ => {},
+			};
+		}
+		Some(())
+	}
+
+	//each note is namesz, descsz, type, then the name and desc padded to 4 bytes
+	fn parse_notes(&mut self, notes: &[u8]) -> Option<()> {
+		let mut off = 0;
+		while off + 12 <= notes.len() {
+			let namesz = read_u32(notes, off)? as usize;
+			let descsz = read_u32(notes, off + 4)? as usize;
+			let note_type = read_u32(notes, off + 8)?;
+			let desc_off = off + 12 + ((namesz + 3) & !3);
+			let desc = notes.get(desc_off..desc_off + descsz)?;
+			let name = notes.get(off + 12..off + 12 + namesz)?;
+			off = desc_off + ((descsz + 3) & !3);
+
+			if name != b"CORE\0" {
+				continue;
+			}
+			match note_type {
+				NT_PRSTATUS => {
+					let signo = read_u32(desc, 0)? as i32;
+					self.threads.push(core_thread {
+						tid: Pid::from_raw(read_u32(desc, 32)? as i32),
+						sig: Signal::try_from(signo).ok(),
+						regs: regs_from_bytes(desc.get(PRSTATUS_REGS_OFF..PRSTATUS_REGS_OFF + REGS_SIZE)?),
+					});
+				},
+				NT_PRPSINFO => {
+					self.pid = Pid::from_raw(read_u32(desc, 24)? as i32);
+				},
+				NT_AUXV => {
+					for entry in desc.chunks_exact(16) {
+						let a_type = read_u64(entry, 0)?;
+						if a_type == 0 {
+							break;
+						}
+						self.auxv.push((a_type, read_u64(entry, 8)?));
+					}
+				},
+				NT_FILE => {
+					let count = read_u64(desc, 0)? as usize;
+					let page_size = read_u64(desc, 8)?;
+					//names come after all the (start, end, offset) entries
+					let mut names = desc.get(count.checked_mul(24)?.checked_add(16)?..)?.split(|byte| *byte == 0);
+					for i in 0..count {
+						let entry = 16 + i*24;
+						self.files.push(file_mapping {
+							start: read_u64(desc, entry)?,
+							end: read_u64(desc, entry + 8)?,
+							offset: read_u64(desc, entry + 16)?.checked_mul(page_size)?,
+							path: String::from_utf8_lossy(names.next()?).into_owned(),
+						});
+					}
+				},
+				_ => {},
+			};
+		}
+		Some(())
+	}
+
+	pub fn get_regs(&self, tid: Pid) -> Result<user_regs_struct, Errno> {
+		match self.threads.iter().find(|thread| thread.tid == tid) {
+			Some(thread) => Ok(thread.regs),
+			None => Err(Errno::ESRCH),
+		}
+	}
+
+	//reads a word. Pages that werent dumped are read from the mapped file, if its around
+	pub fn read_mem(&self, addr: usize) -> Result<u64, Errno> {
+		let mut word = [0u8; 8];
+		for (i, byte) in word.iter_mut().enumerate() {
+			*byte = self.read_byte(addr as u64 + i as u64).ok_or(Errno::EIO)?;
+		}
+		Ok(u64::from_le_bytes(word))
+	}
+
+	fn read_byte(&self, addr: u64) -> Option<u8> {
+		for seg in &self.segments {
+			if addr < seg.vaddr || addr - seg.vaddr >= seg.memsz {
+				continue;
+			}
+			if addr - seg.vaddr < seg.filesz {
+				return self.data.get(seg.offset.checked_add(addr - seg.vaddr)? as usize).cloned();
+			}
+			break;
+		}
+		for file in &self.files {
+			if file.start <= addr && addr < file.end {
+				let mut cache = self.file_cache.borrow_mut();
+				if !cache.contains_key(&file.path) {
+					cache.insert(file.path.clone(), fs::read(&file.path).ok());
+				}
+				return cache[&file.path].as_ref()?.get(file.offset.checked_add(addr - file.start)? as usize).cloned();
+			}
+		}
+		None
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::env;
+	use std::process;
+
+	//ELF header of a core with one PT_NOTE holding notes, straight after the one program header
+	fn core_with_notes(notes: &[u8]) -> Vec<u8> {
+		let mut data = Vec::new();
+		data.extend_from_slice(b"\x7fELF\x02\x01\x01\x00");
+		data.extend_from_slice(&[0; 8]);
+		data.extend_from_slice(&ET_CORE.to_le_bytes());
+		data.extend_from_slice(&62u16.to_le_bytes());
+		data.extend_from_slice(&1u32.to_le_bytes());
+		data.extend_from_slice(&0u64.to_le_bytes());
+		data.extend_from_slice(&64u64.to_le_bytes());
+		data.extend_from_slice(&0u64.to_le_bytes());
+		data.extend_from_slice(&0u32.to_le_bytes());
+		data.extend_from_slice(&64u16.to_le_bytes());
+		data.extend_from_slice(&56u16.to_le_bytes());
+		data.extend_from_slice(&1u16.to_le_bytes());
+		data.extend_from_slice(&[0; 6]);
+		//p_type, p_flags, p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_align
+		data.extend_from_slice(&PT_NOTE.to_le_bytes());
+		data.extend_from_slice(&0u32.to_le_bytes());
+		data.extend_from_slice(&120u64.to_le_bytes());
+		data.extend_from_slice(&[0; 16]);
+		data.extend_from_slice(&(notes.len() as u64).to_le_bytes());
+		data.extend_from_slice(&[0; 16]);
+		data.extend_from_slice(notes);
+		data
+	}
+
+	fn note(note_type: u32, desc: &[u8]) -> Vec<u8> {
+		let mut buf = Vec::new();
+		buf.extend_from_slice(&5u32.to_le_bytes());
+		buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+		buf.extend_from_slice(&note_type.to_le_bytes());
+		buf.extend_from_slice(b"CORE\0\0\0\0");
+		buf.extend_from_slice(desc);
+		while buf.len() % 4 != 0 {
+			buf.push(0);
+		}
+		buf
+	}
+
+	fn open(name: &str, data: &[u8]) -> Result<CoreFile, String> {
+		let path = env::temp_dir().join(format!("{}.{}", name, process::id()));
+		fs::write(&path, data).unwrap();
+		let core = CoreFile::New(path.to_str().unwrap());
+		fs::remove_file(&path).unwrap();
+		core
+	}
+
+	#[test]
+	fn not_a_core() {
+		let err = open("not_a_core", b"#!/bin/sh\n").err().unwrap();
+		assert!(err.ends_with("is not a valid x86_64 ELF core file"));
+	}
+
+	#[test]
+	fn no_threads() {
+		let auxv = note(NT_AUXV, &[0; 16]);
+		let err = open("no_threads", &core_with_notes(&auxv)).err().unwrap();
+		assert!(err.ends_with("has no threads in it"));
+	}
+
+	#[test]
+	fn file_note_and_thread() {
+		let mut status = vec![0u8; PRSTATUS_REGS_OFF + REGS_SIZE + 8];
+		status[0] = 11;
+		status[32..36].copy_from_slice(&1234u32.to_le_bytes());
+		//2 files. Paths follow all the (start, end, page offset) entries
+		let mut files = Vec::new();
+		for val in &[2u64, 0x1000, 0x1000, 0x2000, 3, 0x5000, 0x6000, 0] {
+			files.extend_from_slice(&val.to_le_bytes());
+		}
+		files.extend_from_slice(b"/lib/a.so\0/bin/b\0");
+		let mut notes = note(NT_PRSTATUS, &status);
+		notes.extend(note(NT_FILE, &files));
+
+		let core = open("file_note", &core_with_notes(&notes)).unwrap();
+		//no prpsinfo, so the pid is the first threads
+		assert_eq!(core.pid, Pid::from_raw(1234));
+		assert_eq!(core.threads[0].sig, Some(Signal::SIGSEGV));
+		assert_eq!(core.files.len(), 2);
+		assert_eq!((core.files[0].start, core.files[0].end, core.files[0].offset), (0x1000, 0x2000, 0x3000));
+		assert_eq!(core.files[0].path, "/lib/a.so");
+		assert_eq!((core.files[1].start, core.files[1].offset), (0x5000, 0));
+		assert_eq!(core.files[1].path, "/bin/b");
+	}
+
+	#[test]
+	fn cut_off_note() {
+		let mut notes = note(NT_PRSTATUS, &[0; 64]);
+		notes.truncate(40);
+		assert!(open("cut_off_note", &core_with_notes(&notes)).is_err());
+	}
+
+	#[test]
+	fn huge_sizes() {
+		//so many files the size of their entries overflows
+		let mut files = Vec::new();
+		files.extend_from_slice(&(u64::MAX / 8).to_le_bytes());
+		files.extend_from_slice(&0x1000u64.to_le_bytes());
+		assert!(open("huge_count", &core_with_notes(&note(NT_FILE, &files))).is_err());
+		//note segment going past the end of the address space
+		let mut data = core_with_notes(&note(NT_AUXV, &[0; 16]));
+		data[96..104].copy_from_slice(&u64::MAX.to_le_bytes());
+		assert!(open("huge_note", &data).is_err());
+	}
+}
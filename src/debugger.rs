@@ -1,22 +1,45 @@
 use nix::unistd::Pid;
 use nix::errno::Errno;
 use nix::sys::{wait, ptrace};
+use nix::sys::wait::{WaitStatus, WaitPidFlag};
+use nix::sys::ptrace::Event;
+use nix::sys::signal::{self, Signal};
 use rustyline::{Editor,Helper};
+use rustyline::error::ReadlineError;
 
-use std::collections::HashMap;
-use std::fs::{File};
+use std::collections::{HashMap, VecDeque};
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
 use std::io::{BufReader,BufRead,Write};
 
 use core::ffi::c_void;
 use core::ops::Range;
+use libc::user_regs_struct;
+use regex::Regex;
 
-use crate::breakpoint::{breakpoint, bp_storage};
+use crate::breakpoint::{bp_storage, bp_entry, Disposition};
 use crate::misc::*;
 use crate::format::*;
-use crate::dwarf_functionality::{get_func_from_pc, line_stuff};
+use crate::dwarf_functionality::{get_func_from_pc, line_stuff, eval_location, dwarf_reg_name, return_type, ret_type};
+use crate::expr::{ExprEnv, parse_expr, eval, truncate, int_type};
+use crate::watchpoint::{hw_slots, watchpoint, WatchKind, mask_value, soft_watch, WatchTarget, format_watched, read_bytes};
+use crate::prog_info::ProgInfo;
 use crate::trace::{TraceState, Trace};
-
-use gimli::read::Dwarf;
+use crate::launch::{LaunchConfig, attach, exe_of};
+use crate::thread::{ThreadTable, ThreadState, get_tasks, tgid_of};
+use crate::inferior::{inferior, ForkMode, list_inferiors};
+use crate::signals::{SignalTable, parse_signal, describe, describe_siginfo};
+use crate::load_bias::{find_load_bias, parse_elf_layout};
+use crate::core_dump::write_core;
+use crate::core_file::CoreFile;
+use crate::target::{Target, LiveTarget};
+use crate::script::Script;
+use crate::session::{Session, saved_bp, saved_watch};
+use crate::catchpoint::{CatchKind, parse_catch};
+use crate::dprintf::dprintf_fmt;
+use crate::disasm::{decode, insn, MAX_INSN_LEN};
+use crate::syscalls::{syscall_name, syscall_entering, format_call, format_return};
+use crate::auxv::{auxv_get, auxv_name, AT_ENTRY, AT_BASE};
 
 use serde_json::{to_string};
 
@@ -24,24 +47,784 @@ pub enum dbg_cmd {
 	Exit,
 	Restart,
 	Continue,
+	//already ptrace-attached to the new process. Main loop swaps over to it
+	Attach(Pid),
+	//open a core file (given on the command line) instead of a process
+	Core(String),
+	//restart, then run to the entry point or main
+	Start(StartStop),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StartStop {
+	Entry,
+	Main,
+}
+
+#[derive(PartialEq)]
+pub enum ProcState {
+	Stopped,
+	//we have let go of the process. Nothing can be done until run/attach
+	Detached,
+	//looking at a core file. State can be read but nothing can be run
+	PostMortem,
+	//process has finished, either by itself (exit code), by a signal, or by the kill command
+	Exited(i32),
+	Signaled(Signal),
+	Killed,
+}
+
+//why the process last stopped. Kept so later commands (info program) can ask
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+	//(index, address). rip has already been moved back onto the address
+	Breakpoint(usize, usize),
+	//id of a hardware or software watchpoint
+	Watchpoint(usize),
+	Catchpoint(usize),
+	Signal(Signal),
+	Step,
+}
+
+impl StopReason {
+	pub fn describe(&self) -> String {
+		match self {
+			StopReason::Breakpoint(idx, addr) => format!("at breakpoint {} ({:#x})", idx, addr),
+			StopReason::Watchpoint(idx) => format!("at watchpoint {}", idx),
+			StopReason::Catchpoint(idx) => format!("at catchpoint {}", idx),
+			StopReason::Signal(sig) => format!("by signal {}, {}", sig.as_str(), describe(*sig)),
+			StopReason::Step => String::from("after stepping"),
+		}
+	}
 }
 
 pub struct Debugger<'a> {
 	pub m_pid: Pid, //pid of child
+	//every thread of the child. regs, mem and si act on the selected one
+	pub threads: ThreadTable,
 	pub bp_table: bp_storage,
 	pub trace_file: Trace<'a>,
 	pub trace_state: TraceState,
+	//true if we attached to a running process rather than launching it
+	//decides if we kill or detach from it when done
+	pub attached: bool,
+	pub state: ProcState,
+
+	//number of the current inferior (process). The others are kept stopped in inferiors
+	pub inferior_num: usize,
+	pub inferiors: Vec<inferior>,
+	next_inferior_num: usize,
+	pub follow_fork: ForkMode,
+	pub detach_on_fork: bool,
+	//stop/print/pass for each signal the debugee gets
+	pub signals: SignalTable,
+	//forked children whose initial stop turned up before the fork event did
+	early_children: Vec<Pid>,
+	//set when the debugee execs a new program, so its debug info can be loaded
+	exec_prog: Option<String>,
+	//how far a PIE program has been moved from its link-time addresses. 0 if it isnt PIE
+	pub load_bias: usize,
+	//program and bias last told to the user. Carried across restarts so the same one isnt printed every run
+	pub shown_bias: Option<(String, usize)>,
+	//AT_ENTRY and AT_BASE from the auxv. Where the program starts, and where the dynamic loader is
+	pub entry: Option<usize>,
+	pub interp_base: Option<usize>,
+	//where memory and registers are read from. The live process, or a core file
+	pub target: Box<dyn Target>,
+	//hardware breakpoints and watchpoints, in the debug registers of every thread
+	pub hw: hw_slots,
+	//watch -s. Ids come from bp_table, the same as hardware watchpoints and breakpoints
+	pub soft_watches: Vec<soft_watch>,
+	//catch syscall/signal/fork/exec/exit. None for deleted ones
+	pub catches: Vec<Option<CatchKind>>,
+	//commands <id> is reading in a list for that breakpoint. Lines go into it until "end"
+	recording: Option<(usize, Vec<String>)>,
+	//commands of the breakpoint that was just hit. The main loop runs these before asking for more input
+	pending: VecDeque<String>,
+	//next, finish and start. The thread being stepped, the internal int3 it should come back to, and the stack pointer it will have once it has
+	returning: Option<(Pid, usize, u64)>,
+	//set dprintf-log. Where dprintf output goes instead of the console (path, and the file open for appending)
+	dprintf_log: Option<(String, File)>,
+	//thread that caused the last stop, and why. None until something stops it (or once it has run again)
+	pub last_stop: Option<(Pid, StopReason)>,
+	//set when the current command fails
+	failed: Cell<bool>,
 }
 
 
 impl Debugger<'_> {
 	pub fn New(child: Pid) -> Debugger<'static> {
+		let mut dbg = Debugger::base(child);
+		dbg.set_trace_options();
+		dbg.refresh_thread_regs();
+		dbg
+	}
+
+	//for post-mortem debugging. Threads are as they were when the core was dumped
+	pub fn Core(core: CoreFile) -> Result<Debugger<'static>, String> {
+		//CoreFile::New already refuses these, but dont count on it
+		let first = match core.threads.first() {
+			Some(thread) => (thread.tid, thread.sig),
+			None => {return Err(format!("{} has no threads in it", core.path));},
+		};
+		let mut dbg = Debugger::base(core.pid);
+		dbg.threads = ThreadTable::New(first.0);
+		for thread in &core.threads {
+			dbg.threads.add(thread.tid, ThreadState::Stopped);
+			let info = dbg.threads.get(thread.tid).unwrap();
+			info.regs = Some(thread.regs);
+			info.stop_sig = thread.sig;
+		}
+		println!("Core was generated from process {} ({} threads)", core.pid, core.threads.len());
+		if let Some(sig) = first.1 {
+			println!("Program terminated with signal {}, {}.", sig.as_str(), describe(sig));
+		}
+		dbg.state = ProcState::PostMortem;
+		dbg.target = Box::new(core);
+		Ok(dbg)
+	}
+
+	fn base(child: Pid) -> Debugger<'static> {
 		Debugger {
 			m_pid: child,
-			bp_table: bp_storage::New(),
+			threads: ThreadTable::New(child),
+			bp_table: bp_storage::New(child),
 			trace_file: Trace::New(),
 			trace_state: TraceState::Disabled,
+			attached: false,
+			state: ProcState::Stopped,
+			inferior_num: 1,
+			inferiors: Vec::new(),
+			next_inferior_num: 2,
+			follow_fork: ForkMode::Parent,
+			detach_on_fork: true,
+			signals: SignalTable::New(),
+			early_children: Vec::new(),
+			exec_prog: None,
+			load_bias: 0,
+			shown_bias: None,
+			entry: None,
+			interp_base: None,
+			target: Box::new(LiveTarget),
+			hw: hw_slots::New(),
+			soft_watches: Vec::new(),
+			catches: Vec::new(),
+			recording: None,
+			pending: VecDeque::new(),
+			dprintf_log: None,
+			returning: None,
+			last_stop: None,
+			failed: Cell::new(false),
+		}
+	}
+
+	//for a process that has already been attached to (and is stopped)
+	pub fn Attached(pid: Pid) -> Debugger<'static> {
+		let mut dbg = Debugger::New(pid);
+		dbg.attached = true;
+		for tid in get_tasks(pid) {
+			dbg.threads.add(tid, ThreadState::Stopped);
+		}
+		dbg.set_trace_options();
+		dbg.refresh_thread_regs();
+		dbg
+	}
+
+	//works out where the program was loaded. Needs redoing whenever the program changes (exec)
+	//also picks up the entry point and dynamic loader address while its at it
+	pub fn update_load_bias(&mut self, prog: &ProgInfo) {
+		let auxv = self.target.auxv(self.m_pid);
+		self.entry = auxv_get(&auxv, AT_ENTRY).map(|entry| entry as usize);
+		self.interp_base = auxv_get(&auxv, AT_BASE).filter(|base| *base != 0).map(|base| base as usize);
+
+		self.load_bias = 0;
+		let layout = match &prog.layout {
+			Some(layout) => layout,
+			None => {return;},
+		};
+		match find_load_bias(&*self.target, self.m_pid, &prog.path, layout) {
+			Some(bias) => {
+				self.load_bias = bias as usize;
+				let shown = Some((prog.path.clone(), self.load_bias));
+				if layout.pie && self.shown_bias != shown {
+					println!("{} is position independent. Loaded with bias {:#x}", prog.path, bias);
+					self.shown_bias = shown;
+				}
+			},
+			None => {
+				println!("Could not find where {} was loaded. Addresses will be used as is", prog.path);
+			},
+		};
+	}
+
+	//starti / start. Called on a freshly launched process, still stopped at its exec
+	pub fn start(&mut self, at: StartStop, prog: &ProgInfo) {
+		let addr = match at {
+			StartStop::Entry => {
+				match self.entry.or(prog.entry.map(|entry| self.to_runtime(entry as usize))) {
+					Some(addr) => addr,
+					None => {
+						self.fail("Could not find the entry point of the program");
+						return;
+					},
+				}
+			},
+			StartStop::Main => {
+				match self.resolve_location("main", prog) {
+					Ok(addr) => addr,
+					Err(_) => {
+						self.fail("No main function found in the symbol table or debug info");
+						return;
+					},
+				}
+			},
+		};
+		match at {
+			StartStop::Entry => {println!("Running to entry point {:#x}", addr);},
+			StartStop::Main => {println!("Temporary breakpoint at main ({:#x})", addr);},
+		};
+		self.run_to(addr, prog);
+	}
+
+	//runs until addr, with a breakpoint there that only lasts until the process next stops
+	fn run_to(&mut self, addr: usize, prog: &ProgInfo) {
+		let tid = self.cur_tid();
+		//static programs start at the entry point. Nothing to run
+		if Debugger::get_reg(tid, "rip") == Ok(addr as u64) {
+			return;
+		}
+		//internal, the same as next and finish use. Shares the int3 with any user breakpoint there
+		if let Err(err_num) = self.bp_table.add_internal(addr) {
+			self.fail(&format!("Failed to set temporary breakpoint at {:#x}. Error was {}", addr, err_num));
+			return;
+		}
+		//any stack depth will do
+		self.returning = Some((tid, addr, 0));
+		self.continue_exec(prog);
+		self.returning = None;
+		if self.state == ProcState::Stopped {
+			self.bp_table.remove_internal(addr);
+		}
+	}
+
+	//remembers the breakpoints and settings of this run, for the next one
+	pub fn save_session(&self, session: &mut Session, prog: &ProgInfo) {
+		session.signals = self.signals.clone();
+		session.follow_fork = self.follow_fork;
+		session.detach_on_fork = self.detach_on_fork;
+		session.catches = self.catches.clone();
+		session.dprintf_log = self.dprintf_log.as_ref().map(|(path, _)| path.clone());
+		session.shown_bias = self.shown_bias.clone();
+		//breakpoints cant be set on a core file. Leave the ones from before alone
+		if self.state == ProcState::PostMortem {
+			return;
+		}
+
+		session.prog = prog.path.clone();
+		session.breakpoints.clear();
+		for bp in self.bp_table.bps.values() {
+			let (addr, prog_relative) = self.session_addr(bp.addr, prog);
+			session.breakpoints.push(saved_bp {
+				id: bp.id,
+				addr,
+				prog_relative,
+				enabled: bp.enabled,
+				condition: bp.condition.clone(),
+				ignore_count: bp.ignore_count,
+				disposition: bp.disposition,
+				commands: bp.commands.clone(),
+				dprintf: bp.dprintf.clone(),
+			});
+		}
+		session.bp_groups = self.bp_table.groups.values().cloned().collect();
+		session.next_bp_id = self.bp_table.next_id;
+		session.watchpoints = self.hw.slots.iter().flatten().map(|wp| {
+			let (addr, prog_relative) = self.session_addr(wp.addr, prog);
+			saved_watch { id: wp.id, addr, prog_relative, len: wp.len, kind: wp.kind }
+		}).collect();
+		session.soft_watches = self.soft_watches.iter().map(|watch| {
+			match &watch.target {
+				WatchTarget::Region(addr, len) => {
+					let (addr, prog_relative) = self.session_addr(*addr, prog);
+					(watch.id, WatchTarget::Region(addr, *len), prog_relative)
+				},
+				WatchTarget::Expr(text) => (watch.id, WatchTarget::Expr(text.clone()), false),
+			}
+		}).collect();
+	}
+
+	//an address as its remembered for the next run
+	//only addresses inside the program move with it, and are kept as file addresses. Anything else is kept as is
+	fn session_addr(&self, addr: usize, prog: &ProgInfo) -> (usize, bool) {
+		Debugger::prog_relative(addr, self.load_bias, prog)
+	}
+
+	//same, for the program loaded with load_bias
+	fn prog_relative(addr: usize, load_bias: usize, prog: &ProgInfo) -> (usize, bool) {
+		let file_addr = addr.wrapping_sub(load_bias) as u64;
+		let prog_relative = match &prog.layout {
+			Some(layout) => load_bias != 0 && layout.base_vaddr <= file_addr && file_addr < layout.end_vaddr,
+			None => false,
+		};
+		if prog_relative {(file_addr as usize, true)} else {(addr, false)}
+	}
+
+	//puts the breakpoints and settings from the last run into this one, before it starts running
+	//load bias must already be worked out
+	pub fn rearm(&mut self, session: &mut Session, prog: &ProgInfo) {
+		self.signals = session.signals.clone();
+		self.follow_fork = session.follow_fork;
+		self.detach_on_fork = session.detach_on_fork;
+		self.catches = session.catches.clone();
+		if let Some(path) = &session.dprintf_log {
+			if let Err(err) = self.open_dprintf_log(path) {
+				println!("{}. dprintf output will go to the console", err);
+			}
+		}
+		if self.state == ProcState::PostMortem {
+			return;
+		}
+		//catch exit needs an extra ptrace option
+		self.set_trace_options();
+
+		//addresses mean nothing in a different program
+		if session.prog != prog.path {
+			let count = session.breakpoints.len();
+			if count > 0 {
+				println!("Deleted {} breakpoints set in {}", count, session.prog);
+			}
+			session.breakpoints.clear();
+			session.bp_groups.clear();
+			session.watchpoints.clear();
+			session.soft_watches.clear();
+			session.prog = prog.path.clone();
+			return;
+		}
+
+		for group in &session.bp_groups {
+			self.bp_table.restore_group(group.clone());
+		}
+		//rbreak groups can be big. Their failures are counted up rather than listed
+		let mut group_failures : HashMap<usize, usize> = HashMap::new();
+		for saved in &session.breakpoints {
+			let addr = if saved.prog_relative {self.to_runtime(saved.addr)} else {saved.addr};
+			self.bp_table.restore(saved.id, addr, saved.disposition);
+			let bp = self.bp_table.get_mut(saved.id).unwrap();
+			bp.ignore_count = saved.ignore_count;
+			bp.condition = saved.condition.clone();
+			bp.commands = saved.commands.clone();
+			bp.dprintf = saved.dprintf.clone();
+			if saved.enabled && self.bp_table.enable(saved.id).is_err() {
+				match self.bp_table.group_of(saved.id) {
+					Some(group) => {*group_failures.entry(group.id).or_insert(0) += 1;},
+					None => {println!("Could not re-insert breakpoint {} at {:#x}. It has been disabled", saved.id, addr);},
+				};
+			}
+		}
+		for (group, count) in group_failures {
+			println!("Could not re-insert {} breakpoints of group {} (in a library that isnt loaded yet?). They have been disabled", count, group);
+		}
+		self.bp_table.next_id = self.bp_table.next_id.max(session.next_bp_id);
+
+		//there were never more of these than debug registers, so they all fit again
+		for saved in &session.watchpoints {
+			let addr = if saved.prog_relative {self.to_runtime(saved.addr)} else {saved.addr};
+			let old_value = mask_value(self.read_mem(addr).unwrap_or(0), saved.len);
+			self.hw.insert(watchpoint { id: saved.id, addr, len: saved.len, kind: saved.kind, old_value }).ok();
+		}
+		self.apply_hw();
+
+		let tid = self.cur_tid();
+		for (id, target, prog_relative) in &session.soft_watches {
+			let target = match target {
+				WatchTarget::Region(addr, len) if *prog_relative => WatchTarget::Region(self.to_runtime(*addr), *len),
+				_ => target.clone(),
+			};
+			let old_value = self.watched_value(tid, &target, prog);
+			self.soft_watches.push(soft_watch { id: *id, target, old_value });
+		}
+	}
+
+	//file (link-time, as in DWARF and objdump) address to where it actually is in the process
+	pub fn to_runtime(&self, file_addr: usize) -> usize {
+		file_addr + self.load_bias
+	}
+
+	pub fn to_file(&self, runtime_addr: usize) -> usize {
+		runtime_addr.wrapping_sub(self.load_bias)
+	}
+
+	//addresses of code given by the user
+	//for a PIE program, anything below where it was loaded cant be a runtime address
+	//so its taken to be a file address
+	pub fn parse_code_addr(&self, string: &str) -> Option<usize> {
+		let addr = str_to_int(string)?;
+		if self.load_bias != 0 && addr < self.load_bias {
+			return Some(self.to_runtime(addr));
+		}
+		Some(addr)
+	}
+
+	//thread that regs, mem and si act on
+	pub fn cur_tid(&self) -> Pid {
+		self.threads.selected
+	}
+
+	//needs to be set on every thread we trace. Threads and processes created later inherit them
+	pub fn set_trace_options(&self) {
+		//EXITKILL so that nothing is left running (with int3's in it) if the debugger dies
+		//TRACESYSGOOD tells syscall stops apart from SIGTRAPs (for catch syscall)
+		let mut options = ptrace::Options::PTRACE_O_TRACECLONE | ptrace::Options::PTRACE_O_TRACEFORK |
+			ptrace::Options::PTRACE_O_TRACEVFORK | ptrace::Options::PTRACE_O_TRACEVFORKDONE |
+			ptrace::Options::PTRACE_O_TRACEEXEC | ptrace::Options::PTRACE_O_EXITKILL |
+			ptrace::Options::PTRACE_O_TRACESYSGOOD;
+		//every thread exiting would stop, so only when something wants to know
+		if self.caught(|kind| *kind == CatchKind::Exit).is_some() {
+			options |= ptrace::Options::PTRACE_O_TRACEEXIT;
+		}
+		for tid in self.threads.tids() {
+			if let Err(err_num) = ptrace::setoptions(tid, options) {
+				println!("Failed to set ptrace options on thread {}. Error was {}", tid, err_num);
+			}
+		}
+	}
+
+	//removes all breakpoints and lets the process run free
+	pub fn detach(&mut self) -> Result<(), Errno> {
+		if self.state != ProcState::Stopped {
+			return Ok(());
+		}
+		self.detach_threads()?;
+		self.state = ProcState::Detached;
+		Ok(())
+	}
+
+	//takes the breakpoints out of the current process and detaches every thread of it
+	fn detach_threads(&mut self) -> Result<(), Errno> {
+		//threads that hit a breakpoint already have rip back on it, so the real instruction runs once its restored
+		self.bp_table.remove_all();
+		//debug registers stay set after detaching. The process would get a SIGTRAP it doesnt expect
+		for tid in self.threads.tids() {
+			hw_slots::New().apply(tid).ok();
+		}
+		//every thread is traced seperately, so each needs detaching. Leader last
+		for tid in self.threads.tids() {
+			if tid != self.m_pid {
+				ptrace::detach(tid, None).ok();
+			}
+		}
+		ptrace::detach(self.m_pid, None)?;
+		Ok(())
+	}
+
+	//called when the debugger is done with every process
+	//processes we attached to get detached from (left running), ones we launched get killed
+	pub fn release(&mut self) {
+		self.release_current();
+		while !self.inferiors.is_empty() {
+			let num = self.inferiors[0].num;
+			self.state = ProcState::Detached;
+			self.switch_inferior(num).ok();
+			self.release_current();
+		}
+	}
+
+	fn release_current(&mut self) {
+		if self.state != ProcState::Stopped {
+			return;
+		}
+		if self.attached {
+			if let Err(err_num) = self.detach() {
+				println!("Failed to detach from process {}. Error was {}", self.m_pid, err_num);
+			}
+			return;
+		}
+		if let Err(err_num) = self.kill() {
+			println!("Failed to kill process {}. Error was {}", self.m_pid, err_num);
+		}
+	}
+
+	//kills the current process and reaps it, so no zombie is left behind
+	pub fn kill(&mut self) -> Result<(), Errno> {
+		match signal::kill(self.m_pid, Signal::SIGKILL) {
+			Ok(_) => {},
+			//already dead. Still has to be reaped
+			Err(Errno::ESRCH) => {},
+			Err(err_num) => {return Err(err_num);},
+		};
+		//every thread reports its death seperately. Leader is last
+		let mut tids = self.threads.tids();
+		tids.retain(|tid| *tid != self.m_pid);
+		tids.push(self.m_pid);
+		let mut reaped = Ok(());
+		for tid in tids {
+			loop {
+				match wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+					Ok(WaitStatus::Exited(_, _)) | Ok(WaitStatus::Signaled(_, _, _)) => {break;},
+					//ECHILD is someone else having reaped it already. Anything else and it might still be around
+					Err(err_num) => {
+						if tid == self.m_pid && err_num != Errno::ECHILD {
+							reaped = Err(err_num);
+						}
+						break;
+					},
+					//could still have a stop queued up from before the kill
+					Ok(_) => {},
+				};
+			}
+		}
+		self.threads.threads.clear();
+		self.state = ProcState::Killed;
+		reaped
+	}
+
+	//if the wait status says the process is gone, records how and tells the user
+	//returns true if it is gone
+	fn process_gone(&mut self, status: &WaitStatus) -> bool {
+		match *status {
+			WaitStatus::Exited(_, code) => {
+				println!("Process {} exited with code {}", self.m_pid, code);
+				self.state = ProcState::Exited(code);
+			},
+			WaitStatus::Signaled(_, sig, _) => {
+				println!("Process {} was killed by {}, {}", self.m_pid, sig.as_str(), describe(sig));
+				self.state = ProcState::Signaled(sig);
+			},
+			_ => {return false;},
+		};
+		self.threads.threads.clear();
+		true
+	}
+
+	//for commands that need a live, stopped process. Says why not if there isnt one
+	fn check_live(&self) -> bool {
+		match self.state {
+			ProcState::Stopped => {return true;},
+			ProcState::Detached => {self.fail("The program is not being debugged. Use run or attach first");},
+			ProcState::PostMortem => {self.fail("The program is not running, this is a core file. Use run to start it");},
+			ProcState::Exited(code) => {self.fail(&format!("The program has exited with code {}. Use run to start it again", code));},
+			ProcState::Signaled(sig) => {self.fail(&format!("The program was killed by {}. Use run to start it again", sig.as_str()));},
+			ProcState::Killed => {self.fail("The program has been killed. Use run to start it again");},
+		};
+		false
+	}
+
+	//makes a stopped inferior the current one. The old current one is kept stopped, unless its gone
+	fn switch_inferior(&mut self, num: usize) -> Result<(), ()> {
+		let idx = match self.inferiors.iter().position(|inf| inf.num == num) {
+			Some(idx) => idx,
+			None => {return Err(());},
+		};
+		let other = self.inferiors.remove(idx);
+		let old = inferior {
+			num: self.inferior_num,
+			pid: self.m_pid,
+			threads: std::mem::replace(&mut self.threads, other.threads),
+			bp_table: std::mem::replace(&mut self.bp_table, other.bp_table),
+			attached: self.attached,
+			load_bias: self.load_bias,
+		};
+		if self.state == ProcState::Stopped {
+			self.inferiors.push(old);
+		}
+		self.inferior_num = other.num;
+		self.m_pid = other.pid;
+		self.attached = other.attached;
+		self.load_bias = other.load_bias;
+		self.state = ProcState::Stopped;
+		Ok(())
+	}
+
+	//a thread of the current process has forked (or vforked)
+	//depending on the settings we either stay with the parent or move to the child
+	//and the other one is either detached or kept as a stopped inferior
+	//Leaves whichever process we follow stopped. Returns the thread of it that should carry on
+	fn handle_fork(&mut self, tid: Pid, child: Pid, vfork: bool, prog: &ProgInfo) -> Pid {
+		//the child's memory is a copy of the parents, so it has all the int3's too
+		let mut child_bps = self.bp_table.clone_for(child);
+
+		if self.follow_fork == ForkMode::Parent {
+			//the parent of a vfork cant run until the child execs or exits, so the child cant be held stopped
+			if self.detach_on_fork || vfork {
+				//for vfork the memory is shared, so this takes them out of the parent too
+				//they get put back once the vfork is done
+				child_bps.remove_all();
+				ptrace::detach(child, None).ok();
+				println!("Detaching after fork from child process {}", child);
+			}
+			else {
+				let num = self.next_inferior_num;
+				self.next_inferior_num += 1;
+				self.inferiors.push(inferior {
+					num,
+					pid: child,
+					threads: ThreadTable::New(child),
+					bp_table: child_bps,
+					attached: self.attached,
+					load_bias: self.load_bias,
+				});
+				println!("Inferior {} (process {}) added after fork", num, child);
+			}
+			return tid;
+		}
+
+		//following the child. Parent has to be fully stopped before its let go of or put aside
+		if let Some(thread) = self.threads.get(tid) {
+			thread.state = ThreadState::Stopped;
+			thread.stop_sig = None;
+		}
+		self.stop_all_threads(prog);
+		if self.detach_on_fork {
+			//for vfork the child loses the breakpoints too, but they go back in when it execs
+			if let Err(err_num) = self.detach_threads() {
+				println!("Failed to detach from parent process {}. Error was {}", self.m_pid, err_num);
+			}
+			println!("Detaching after fork from parent process {}", self.m_pid);
+		}
+		else {
+			self.refresh_thread_regs();
+			self.inferiors.push(inferior {
+				num: self.inferior_num,
+				pid: self.m_pid,
+				threads: std::mem::replace(&mut self.threads, ThreadTable::New(child)),
+				bp_table: std::mem::replace(&mut self.bp_table, bp_storage::New(child)),
+				attached: self.attached,
+				load_bias: self.load_bias,
+			});
+			println!("Inferior {} (process {}) kept stopped after fork", self.inferior_num, self.m_pid);
+		}
+
+		self.inferior_num = self.next_inferior_num;
+		self.next_inferior_num += 1;
+		self.m_pid = child;
+		self.threads = ThreadTable::New(child);
+		self.threads.get(child).unwrap().state = ThreadState::Running;
+		self.bp_table = child_bps;
+		//debug registers arent copied to the child
+		self.hw.apply(child).ok();
+		println!("Following child process {} (inferior {})", child, self.inferior_num);
+		child
+	}
+
+	//the current process has replaced its image. Old threads are gone, and the
+	//breakpoints need to go into the new image
+	//prog is still the program from before the exec
+	fn handle_exec(&mut self, prog: &ProgInfo) {
+		let exe = exe_of(self.m_pid).unwrap_or_default();
+		println!("Process {} is executing new program: {}", self.m_pid, exe);
+		self.threads = ThreadTable::New(self.m_pid);
+
+		//same program again. Whatever was in it moves with the load bias, the same as on a restart
+		//a different program has nothing at those addresses, so they cant go in at all
+		let new_bias = match &prog.layout {
+			Some(layout) if prog.is_file(&exe) => find_load_bias(&*self.target, self.m_pid, &prog.path, layout),
+			None if prog.is_file(&exe) => Some(0),
+			_ => None,
+		};
+		let old_bias = self.load_bias;
+		let moved = |addr: usize| -> Option<usize> {
+			let bias = new_bias? as usize;
+			match Debugger::prog_relative(addr, old_bias, prog) {
+				(file_addr, true) => Some(file_addr + bias),
+				(addr, false) => Some(addr),
+			}
+		};
+		let failed = self.bp_table.relocate(moved);
+
+		let mut dropped = Vec::new();
+		for idx in 0..self.hw.slots.len() {
+			let wp = match &self.hw.slots[idx] {
+				Some(wp) => *wp,
+				None => {continue;},
+			};
+			match moved(wp.addr) {
+				Some(addr) => {
+					let old_value = mask_value(self.read_mem(addr).unwrap_or(0), wp.len);
+					self.hw.slots[idx] = Some(watchpoint { addr, old_value, ..wp });
+				},
+				None => {
+					dropped.push(wp.id);
+					self.hw.slots[idx] = None;
+				},
+			};
+		}
+		let mut soft_watches = std::mem::take(&mut self.soft_watches);
+		soft_watches.retain_mut(|watch| match watch.target {
+			WatchTarget::Region(addr, len) => match moved(addr) {
+				Some(addr) => {
+					watch.target = WatchTarget::Region(addr, len);
+					true
+				},
+				None => {
+					dropped.push(watch.id);
+					false
+				},
+			},
+			WatchTarget::Expr(_) => true,
+		});
+		self.soft_watches = soft_watches;
+		if let Some(bias) = new_bias {
+			self.load_bias = bias as usize;
+		}
+
+		if new_bias.is_some() {
+			for idx in failed {
+				println!("Could not re-insert breakpoint {} in the new program. It has been disabled", idx);
+			}
 		}
+		else if !failed.is_empty() {
+			let ids : Vec<String> = failed.iter().map(|id| id.to_string()).collect();
+			println!("Breakpoints {} were set in {}, not the new program. They have been disabled", ids.join(", "), prog.path);
+		}
+		if !dropped.is_empty() {
+			dropped.sort();
+			let ids : Vec<String> = dropped.iter().map(|id| id.to_string()).collect();
+			println!("Watchpoints {} were set in {}, not the new program. They have been deleted", ids.join(", "), prog.path);
+		}
+		//exec clears the debug registers too
+		self.hw.apply(self.m_pid).ok();
+		self.exec_prog = Some(exe);
+	}
+
+	//events that are dealt with as they come in, no matter if we are waiting for a stop or stopping threads
+	//returns false if it isnt one of them
+	fn handle_side_event(&mut self, tid: Pid, event: i32) -> bool {
+		if event == Event::PTRACE_EVENT_CLONE as i32 {
+			//the new thread might have already reported its initial stop (and been added)
+			if let Ok(new_tid) = ptrace::getevent(tid) {
+				let new_tid = Pid::from_raw(new_tid as i32);
+				if !self.threads.contains(new_tid) {
+					let num = self.threads.add(new_tid, ThreadState::Starting);
+					println!("New thread {} (tid {})", num, new_tid);
+				}
+			}
+			true
+		}
+		else if event == Event::PTRACE_EVENT_VFORK_DONE as i32 {
+			//vfork child has exec'd or exited. Parent has its memory back, so breakpoints can go back in
+			self.bp_table.reinsert();
+			true
+		}
+		else {
+			false
+		}
+	}
+
+	//child of a fork. Waits for its initial stop (if it hasnt turned up already)
+	fn take_fork_child(&mut self, tid: Pid) -> Option<Pid> {
+		let child = match ptrace::getevent(tid) {
+			Ok(child) => Pid::from_raw(child as i32),
+			Err(_) => {return None;},
+		};
+		match self.early_children.iter().position(|pid| *pid == child) {
+			Some(idx) => {
+				self.early_children.remove(idx);
+			},
+			None => {
+				wait::waitpid(child, Some(WaitPidFlag::__WALL)).ok();
+			},
+		};
+		Some(child)
 	}
 
 	
@@ -70,29 +853,62 @@ impl Debugger<'_> {
 		}
 	}
 
+	//a command has gone wrong. Scripts need to know, as well as the user
+	fn fail(&self, msg: &str) {
+		println!("{}", msg);
+		self.failed.set(true);
+	}
+
 	pub fn read_mem(&self, target_addr: usize) -> Result<u64, Errno> {
-		match ptrace::read(self.m_pid, target_addr as *mut c_void) {
-			Ok(mem_val) => Ok(mem_val as u64),
-			Err(err_num) => Err(err_num),
-		}
+		self.target.read_mem(self.cur_tid(), target_addr)
 	}
 
 	pub fn write_mem(&self, target_addr: usize, data: u64) {
-		unsafe { ptrace::write(self.m_pid, target_addr as *mut c_void, data as *mut c_void); }
+		unsafe { ptrace::write(self.cur_tid(), target_addr as *mut c_void, data as *mut c_void).ok(); }
 	}
 
 	
-	//the bool returned indicates if debugger is to restart
-	//the restart and exit commands are handled through this return statement
-	pub fn run<T: Helper>(&mut self, inputHandler: &mut Editor::<T>, dwarf_info: &Dwarf<gimli::EndianSlice<gimli::RunTimeEndian>>) -> bool {
-		//wait for child to startup. It sends signa when its finished setting up
-		wait::waitpid(self.m_pid, None);
+	//the dbg_cmd returned tells the main loop what to do next (exit, restart or swap to an attached process)
+	//the restart, exit and attach commands are handled through this return statement
+	//process must already be stopped (after its exec, or after attaching)
+	//script commands are run first. In batch mode thats all thats run, otherwise the user takes over after
+	pub fn run<T: Helper>(&mut self, inputHandler: &mut Editor::<T>, script: &mut Script, prog: &mut ProgInfo, launch: &mut LaunchConfig) -> dbg_cmd {
 		//setting up rustyline 
 	
 		loop {
-			let inputLine = inputHandler.readline("dbg> ");
+			//a breakpoints commands go before anything else. One of them might continue and queue up more
+			if let Some(line) = self.pending.pop_front() {
+				self.failed.set(false);
+				match self.handle_command(&line, prog, launch) {
+					dbg_cmd::Continue => {},
+					other => {return other;},
+				};
+				continue;
+			}
+			if let Some(line) = script.next_line() {
+				self.failed.set(false);
+				let result = self.handle_command(&line, prog, launch);
+				if self.failed.get() && script.command_failed(&line) && script.batch {
+					return dbg_cmd::Exit;
+				}
+				match result {
+					dbg_cmd::Continue => {},
+					other => {return other;},
+				};
+				continue;
+			}
+			if script.batch {
+				return dbg_cmd::Exit;
+			}
+
+			let prompt = if self.recording.is_some() {">"} else {"dbg> "};
+			let inputLine = inputHandler.readline(prompt);
 			let inputLine = match inputLine {
 				Ok(line) => {line},
+				//ctrl-c just gives a fresh prompt
+				Err(ReadlineError::Interrupted) => {continue;},
+				//ctrl-d / end of input is the same as exit
+				Err(ReadlineError::Eof) => {return dbg_cmd::Exit;},
 				Err(_) =>  {panic!("Error reading input");},
 			};
 			//might seem counterintuitve to add command to history before handling it
@@ -100,15 +916,30 @@ impl Debugger<'_> {
 			//we record all commands to history, not just valid ones (so that small typos can be recorded and fixed)
 			//so its fine. We do need to clone though because it moves the value into the editor
 			inputHandler.add_history_entry(inputLine.clone());
-			match self.handle_command(&inputLine, dwarf_info) {
+			match self.handle_command(&inputLine, prog, launch) {
 				dbg_cmd::Continue => {},
-				dbg_cmd::Exit => {return false;},
-				dbg_cmd::Restart => {return true;},
+				other => {return other;},
 			};
 		}
 	}
 
-	fn handle_command(&mut self, command: &str, dwarf_info: &Dwarf<gimli::EndianSlice<gimli::RunTimeEndian>>) -> dbg_cmd {
+	fn handle_command(&mut self, command: &str, prog: &mut ProgInfo, launch: &mut LaunchConfig) -> dbg_cmd {
+		//lines of a commands list are kept, not run
+		if let Some((id, mut lines)) = self.recording.take() {
+			let line = command.trim();
+			if line == "end" {
+				self.bp_table.set_commands(id, lines).ok();
+			}
+			else {
+				if !line.is_empty() {
+					lines.push(line.to_string());
+				}
+				self.recording = Some((id, lines));
+			}
+			return dbg_cmd::Continue;
+		}
+		//dprintf wants the format string as it was typed, spaces and all
+		let line = command;
 		let tmp : Vec<&str> = command.split(' ').collect();
 		let mut args : Vec<&str> = vec!();
 		for arg in tmp {
@@ -123,16 +954,62 @@ impl Debugger<'_> {
 		}
 		let command = args[0];
 
+		//commands that touch the process cant be run once we've let go of it, or its died
+		//core files can still be looked at, just not changed
+		let core_ok = self.state == ProcState::PostMortem && reads_state(&args);
+		if needs_process(&args) && !core_ok && !self.check_live() {
+			return dbg_cmd::Continue;
+		}
 
 		let mut dbg_result = dbg_cmd::Continue;
 		//all commands currently supported
 		match command {
 			//sent continue signal and wait for next pid
 			"continue" | "cont" | "c" => {
-				self.continue_exec();
+				self.continue_exec(prog);
 			},
 			"break" | "breakpoint" | "b" => {
-				self.handle_breakpoints(args);
+				self.handle_breakpoints(args, prog);
+			},
+			"hbreak" | "watch" | "rwatch" | "awatch" => {
+				self.handle_watch(args, prog);
+			},
+			"rbreak" => {
+				self.handle_rbreak(args, prog);
+			},
+			"catch" => {
+				self.handle_catch(args);
+			},
+			//tbreak <loc> [if <condition>]. Deleted once its hit
+			"tbreak" => {
+				if args.len() < 2 {
+					self.fail("Need location specified for breakpoint");
+					return dbg_result;
+				}
+				self.create_breakpoint(args, prog, Disposition::Delete);
+			},
+			//commands <id>, then one command per line, then end. Run each time the breakpoint is hit
+			//an empty list takes away the ones it had
+			//dprintf <loc> "format", args... Prints each time loc is hit, then carries on
+			"dprintf" => {
+				self.create_dprintf(line, prog);
+			},
+			"commands" => {
+				let id = match args.get(1).and_then(|id| str_to_int(id)) {
+					Some(id) => id,
+					None => {
+						self.fail("Need id of breakpoint to give commands to");
+						return dbg_result;
+					},
+				};
+				//a group (rbreak) gives the same commands to everything in it
+				if self.bp_table.ids_in(id).is_empty() {
+					self.fail(&format!("No breakpoint number {}", id));
+					return dbg_result;
+				}
+				println!("Type commands for breakpoint {}, one per line.", id);
+				println!("End with a line saying just \"end\".");
+				self.recording = Some((id, Vec::new()));
 			},
 			"registers" | "regs" | "r" => {
 				self.handle_regs(args);
@@ -140,8 +1017,35 @@ impl Debugger<'_> {
 			"memory" | "mem" | "m" => {
 				self.handle_mem(args);
 			},
-			"s" | "si" => {
-				ptrace::step(self.m_pid, None);
+			//si [count] / ni [count]. One instruction at a time, ni going over calls
+			"s" | "si" | "ni" => {
+				let count = match args.get(1) {
+					Some(count) => match str_to_int(count) {
+						Some(count) if count > 0 => count,
+						_ => {
+							self.fail(&format!("Invalid count {}", count));
+							return dbg_result;
+						},
+					},
+					None => 1,
+				};
+				self.step_instructions(count, command == "ni", prog);
+			},
+			//step / next. One source line, going into / over calls
+			"step" | "next" | "n" => {
+				self.step_line(command != "step", prog);
+			},
+			"finish" | "fin" => {
+				self.finish(prog);
+			},
+			"kill" => {
+				match self.kill() {
+					Ok(_) => {println!("Process {} killed", self.m_pid);},
+					Err(err_num) => {self.fail(&format!("Failed to kill process {}. Error was {}", self.m_pid, err_num));},
+				};
+			},
+			"thread" | "t" => {
+				self.handle_thread(args);
 			},
 			"exit" => {
 				dbg_result = dbg_cmd::Exit;
@@ -151,141 +1055,1746 @@ impl Debugger<'_> {
 			"restart" => {
 				dbg_result = dbg_cmd::Restart;
 			},
-			"dwarf" => {
-				match get_func_from_pc(dwarf_info, 0x113e) {
-					Some(die) => {println!("{:?}",die);},
-					None => {},
+			//starti / start [args] [< in] [> out]. Like run, but stops at the entry point / main
+			"starti" | "start" => {
+				if args.len() > 1 {
+					if let Err(err) = launch.set_args_and_redirects(&args[1..]) {
+						self.fail(&err);
+						return dbg_result;
+					}
 				}
-				line_stuff(dwarf_info);
+				let at = if command == "starti" {StartStop::Entry} else {StartStop::Main};
+				dbg_result = dbg_cmd::Start(at);
 			},
-			"snapshot"=> {
-				Trace::trace_init(self);	
+			//run [args] [< in] [> out]. Same as restart, but can change the args and redirections first
+			//with no args given, the previous ones are kept
+			"run" => {
+				if args.len() > 1 {
+					if let Err(err) = launch.set_args_and_redirects(&args[1..]) {
+						self.fail(&err);
+						return dbg_result;
+					}
+				}
+				dbg_result = dbg_cmd::Restart;
+			},
+			//attach <pid>
+			"attach" => {
+				if args.len() < 2 {
+					self.fail("Attach command needs a pid");
+					return dbg_result;
+				}
+				let pid = match str_to_int(args[1]) {
+					Some(num) => Pid::from_raw(num as i32),
+					None => {
+						self.fail("Invalid pid specified");
+						return dbg_result;
+					},
+				};
+				match attach(pid) {
+					Ok(_) => {
+						dbg_result = dbg_cmd::Attach(pid);
+					},
+					Err(err_num) => {
+						self.fail(&format!("Failed to attach to process {}. Error was {}", pid, err_num));
+					},
+				};
+			},
+			"detach" => {
+				match self.detach() {
+					Ok(_) => {println!("Detached from process {}", self.m_pid);},
+					Err(err_num) => {self.fail(&format!("Failed to detach. Error was {}", err_num));},
+				};
+			},
+			"set" => {
+				self.handle_set(args, launch);
+			},
+			"unset" => {
+				if args.len() < 3 || args[1] != "env" {
+					self.fail("Unset command needs to be formatted: unset env <var>");
+					return dbg_result;
+				}
+				launch.unset_env(args[2]);
+			},
+			"show" => {
+				if args.len() > 1 && args[1] == "env" {
+					launch.print_env();
+				}
+				else {
+					launch.print();
+					println!("follow-fork-mode: {:?}", self.follow_fork);
+					println!("detach-on-fork: {}", if self.detach_on_fork {"on"} else {"off"});
+					match &self.dprintf_log {
+						Some((path, _)) => {println!("dprintf-log: {}", path);},
+						None => {println!("dprintf-log: off (console)");},
+					};
+				}
+			},
+			//dwarf [addr]. Looks up the given address, or where the current thread is
+			"dwarf" => {
+				if !prog.has_debug_info() {
+					self.fail("No debugging information");
+					return dbg_result;
+				}
+				let addr = if args.len() > 1 {
+					match self.parse_code_addr(args[1]) {
+						Some(addr) => addr,
+						None => {
+							self.fail(&format!("Invalid address {}", args[1]));
+							return dbg_result;
+						},
+					}
+				}
+				else {
+					if self.state != ProcState::PostMortem && !self.check_live() {
+						return dbg_result;
+					}
+					match self.target.get_regs(self.cur_tid()) {
+						Ok(regs) => regs.rip as usize,
+						Err(err_num) => {
+							self.fail(&format!("Failed to retrieve registers with ptrace.\n Error code was {}", err_num));
+							return dbg_result;
+						},
+					}
+				};
+				//DWARF only knows about file addresses
+				let file_addr = self.to_file(addr);
+				println!("{:#x} is file address {:#x}", addr, file_addr);
+				if let Some(die) = get_func_from_pc(&prog.dwarf, file_addr as u64) {
+					println!("{:?}", die);
+				}
+				line_stuff(&prog.dwarf);
+			},
+			//gcore [file]. Defaults to core.<pid>
+			"gcore" => {
+				let path = match args.get(1) {
+					Some(path) => path.to_string(),
+					None => format!("core.{}", self.m_pid),
+				};
+				self.refresh_thread_regs();
+				match write_core(self, &path) {
+					Ok(segments) => {println!("Saved core file {} ({} segments)", path, segments);},
+					Err(err) => {self.fail(&err);},
+				};
+			},
+			"snapshot"=> {
+				Trace::trace_init(self);	
+			},
+			"restore" => {
+				self.restore_trace_entrance(args);
+			},
+			"info" => {
+				self.handle_info(args);
+			},
+			//handle <SIG> [no]stop [no]print [no]pass
+			"handle" => {
+				if let Err(err) = self.signals.handle(&args[1..]) {
+					self.fail(&err);
+				}
+			},
+			//signal <SIG>. Continue, delivering the signal to the current thread (0 for none)
+			"signal" => {
+				if args.len() < 2 {
+					self.fail("Signal command needs a signal to send");
+					return dbg_result;
+				}
+				let sig = if args[1] == "0" {
+					None
+				}
+				else {
+					match parse_signal(args[1]) {
+						Some(sig) => Some(sig),
+						None => {
+							self.fail(&format!("Unknown signal {}", args[1]));
+							return dbg_result;
+						},
+					}
+				};
+				let tid = self.cur_tid();
+				if let Some(thread) = self.threads.get(tid) {
+					thread.pending_sig = sig;
+				}
+				self.continue_exec(prog);
+			},
+			//inferior <num>
+			"inferior" => {
+				if args.len() < 2 {
+					println!("Current inferior is {} (process {})", self.inferior_num, self.m_pid);
+					return dbg_result;
+				}
+				match str_to_int(args[1]) {
+					Some(num) if num == self.inferior_num => {},
+					Some(num) => {
+						match self.switch_inferior(num) {
+							Ok(_) => {println!("Switched to inferior {} (process {})", num, self.m_pid);},
+							Err(_) => {self.fail(&format!("No inferior numbered {}", num));},
+						};
+					},
+					None => {self.fail("Invalid inferior number");},
+				};
+			},
+			_ => {self.fail("Invalid command");},
+		};
+
+		//the debugee exec'd a new program while running. Its debug info replaces the old programs
+		if let Some(exe) = self.exec_prog.take() {
+			prog.reload_if_changed(&exe);
+			self.update_load_bias(prog);
+		}
+		dbg_result
+	}
+
+	//info inferiors
+	//info threads
+	fn handle_info(&mut self, args: Vec<&str>) {
+		if args.len() < 2 {
+			self.fail("Info command needs a subject: inferiors, threads, signals, auxv, program");
+			return;
+		}
+		match args[1] {
+			"auxv" => {
+				let auxv = self.target.auxv(self.m_pid);
+				if auxv.is_empty() {
+					self.fail("No auxiliary vector available");
+					return;
+				}
+				for (a_type, a_val) in auxv {
+					let (name, desc) = auxv_name(a_type);
+					println!("{:<4} {:<20}{:<40}{:#x}", a_type, name, desc, a_val);
+				}
+			},
+			"inferiors" => {
+				list_inferiors(self.inferior_num, self.m_pid, &self.inferiors);
+			},
+			//info signals [SIG]
+			"signals" | "signal" => {
+				if args.len() > 2 {
+					match parse_signal(args[2]) {
+						Some(sig) => {self.signals.print(Some(sig));},
+						None => {self.fail(&format!("Unknown signal {}", args[2]));},
+					};
+				}
+				else {
+					self.signals.print(None);
+				}
+			},
+			"threads" => {
+				if self.state != ProcState::PostMortem && !self.check_live() {
+					return;
+				}
+				self.threads.list();
+			},
+			//why the process is stopped
+			"program" => {
+				if !self.check_live() {
+					return;
+				}
+				match self.last_stop {
+					Some((tid, reason)) => {
+						let num = self.threads.get(tid).map_or(0, |thread| thread.num);
+						println!("Process {} stopped in thread {} (tid {}) {}.", self.m_pid, num, tid, reason.describe());
+					},
+					None => {println!("Process {} is stopped. It hasnt been run since starting or attaching.", self.m_pid);},
+				};
+			},
+			_ => {
+				self.fail(&format!("Unknown info subject {}", args[1]));
+			},
+		};
+	}
+
+	//set args <args...> [< in] [> out]
+	//set env <var>=<val>
+	//set cwd <dir>
+	//These only take effect when the debugee is next (re)started
+	fn handle_set(&mut self, args: Vec<&str>, launch: &mut LaunchConfig) {
+		if args.len() < 2 {
+			self.fail("Set command needs a setting to change");
+			return;
+		}
+
+		match args[1] {
+			"args" => {
+				if let Err(err) = launch.set_args_and_redirects(&args[2..]) {
+					self.fail(&err);
+				}
+			},
+			"env" | "environment" => {
+				if launch.set_env(&args[2..]).is_err() {
+					self.fail("Set env command needs to be formatted: set env <var>=<val>");
+				}
+			},
+			"cwd" => {
+				if args.len() < 3 {
+					launch.cwd = None;
+				}
+				else {
+					launch.cwd = Some(args[2].to_string());
+				}
+			},
+			"follow-fork-mode" => {
+				match args.get(2) {
+					Some(&"parent") => {self.follow_fork = ForkMode::Parent;},
+					Some(&"child") => {self.follow_fork = ForkMode::Child;},
+					_ => {self.fail("Set follow-fork-mode command needs to be formatted: set follow-fork-mode parent|child");},
+				};
+			},
+			"detach-on-fork" => {
+				match args.get(2) {
+					Some(&"on") => {self.detach_on_fork = true;},
+					Some(&"off") => {self.detach_on_fork = false;},
+					_ => {self.fail("Set detach-on-fork command needs to be formatted: set detach-on-fork on|off");},
+				};
+			},
+			//set dprintf-log <file> | off. Appended to, so output from earlier runs is kept
+			"dprintf-log" => {
+				match args.get(2) {
+					Some(&"off") => {self.dprintf_log = None;},
+					Some(path) => {
+						if let Err(err) = self.open_dprintf_log(path) {
+							self.fail(&err);
+						}
+					},
+					None => {self.fail("Set dprintf-log command needs to be formatted: set dprintf-log <file>|off");},
+				};
+			},
+			//off keeps ASLR. PIE programs then load somewhere different each run
+			"disable-randomization" => {
+				match args.get(2) {
+					Some(&"on") => {launch.disable_aslr = true;},
+					Some(&"off") => {launch.disable_aslr = false;},
+					_ => {self.fail("Set disable-randomization command needs to be formatted: set disable-randomization on|off");},
+				};
+			},
+			_ => {
+				self.fail(&format!("Unknown setting {}", args[1]));
+			},
+		};
+	}
+
+	fn restore_trace_entrance(&mut self, args: Vec<&str>) {
+		if args.len() < 2 {
+			self.fail("File must be specified");
+			return;
+		}
+
+		let mut file = match File::open(args[1]) {
+			Ok(result) => result,
+			Err(err)=> {
+				self.fail(&format!("error in opening file. Error was {:?}", err.kind())); 
+				return;
+			},
+		};
+
+		Trace::restore(&mut file, self);
+	}
+
+
+	fn continue_exec(&mut self, prog: &ProgInfo) {
+		//anything after a continue in a breakpoints commands is dropped, the same as gdb
+		self.pending.clear();
+		self.last_stop = None;
+		//every stopped thread that is sitting on a breakpoint needs to get past it first
+		if !self.step_past_breakpoints(prog) {
+			return;
+		}
+		let tid = self.cur_tid();
+
+		//tracing and restoring only follow the selected thread. The rest stay stopped
+		if self.trace_state == TraceState::Tracing {
+			//need to loop because we dont want user to notice us breaking on syscalls
+			//so we speed by for each syscall, and only stop if signal generated is for something besides a syscall (probs a breakpoint)
+			loop {
+				ptrace::syscall(tid, None).ok();
+				if let Ok(status) = wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+					if self.process_gone(&status) {
+						return;
+					}
+				}
+				let siginfo = ptrace::getsiginfo(tid).unwrap();
+			
+				//if we are tracing, we need to get the signal info for each event
+				//For both breakpoints and stoped caused by SYSCALL, the signo is 5
+				//but for sysem the code is also 5 (0x85 with TRACESYSGOOD). Have to check the signo to make sure its not something like a sigsegv signal or smn
+				if siginfo.si_signo == 5 && siginfo.si_code == (libc::SIGTRAP | 0x80) {
+							
+					ptrace::syscall(tid, None).ok();
+					if let Ok(status) = wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+						if self.process_gone(&status) {
+							return;
+						}
+					}
+						
+					let regs = regs_to_dict(ptrace::getregs(tid).unwrap());
+					self.trace_file.syscalls_append(regs);
+				}
+				//it wasnt a syscall signal that caused the pause - probably a breakpoint
+				else {
+					break
+				}
+			}
+			if let Some(thread) = self.threads.get(tid) {
+				thread.stop_sig = Some(Signal::SIGTRAP);
+			}
+			self.refresh_thread_regs();
+		}
+		else if self.trace_state == TraceState::Restoring {
+			ptrace::sysemu(tid, None).ok();
+			if let Ok(status) = wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+				if self.process_gone(&status) {
+					return;
+				}
+			}
+			if let Some(thread) = self.threads.get(tid) {
+				thread.stop_sig = Some(Signal::SIGTRAP);
+			}
+			self.refresh_thread_regs();
+		}
+		else if !self.soft_watches.is_empty() {
+			self.continue_watching(prog);
+		}
+		else {
+			//breakpoints whose condition is false are gone straight past, so this can take many stops
+			loop {
+				self.resume_all();
+				match self.wait_for_stop(prog) {
+					Some((tid, status)) => {
+						if !self.stopped_with(tid, status, prog) && self.step_past_breakpoints(prog) {
+							continue;
+						}
+					},
+					None => {
+						self.lost_process();
+					},
+				};
+				break;
+			}
+		}
+	}
+
+	//something stopped (or finished) the process while it was running. Stops every other thread and reports why
+	//returns false if it should carry on instead (a breakpoint condition was false, or the hit is being ignored)
+	fn stopped_with(&mut self, tid: Pid, status: WaitStatus, prog: &ProgInfo) -> bool {
+		if let WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) = status {
+			self.process_gone(&status);
+			return true;
+		}
+		//all-stop. Thread that caused the stop becomes the selected one
+		let stop_sig = match status {
+			WaitStatus::Stopped(_, sig) => Some(sig),
+			_ => None,
+		};
+		if let Some(thread) = self.threads.get(tid) {
+			thread.state = ThreadState::Stopped;
+			thread.stop_sig = stop_sig;
+		}
+		self.threads.selected = tid;
+		self.stop_all_threads(prog);
+		self.refresh_thread_regs();
+
+		//breakpoints, watchpoints and catchpoints replace this with something more specific
+		if let Some(sig) = stop_sig {
+			self.last_stop = Some((tid, StopReason::Signal(sig)));
+		}
+		let stop = match (status, stop_sig) {
+			(WaitStatus::PtraceSyscall(_), _) => self.syscall_stop(tid),
+			(_, Some(Signal::SIGTRAP)) => match self.hw.hit(tid) {
+				Some(idx) => self.watchpoint_hit(tid, idx, prog),
+				None => self.breakpoint_hit(tid, prog),
+			},
+			(_, Some(sig)) => {
+				if let Some(idx) = self.caught(|kind| kind.catches_signal(sig)) {
+					println!("Catchpoint {} (signal {})", idx, sig.as_str());
+					self.last_stop = Some((tid, StopReason::Catchpoint(idx)));
+				}
+				true
+			},
+			_ => true,
+		};
+		if !stop {
+			return false;
+		}
+		if let Some(sig) = stop_sig {
+			//breakpoints, watchpoints, catchpoints and stepping have already said why it stopped
+			if let Some((_, StopReason::Signal(_))) = self.last_stop {
+				self.report_signal(tid, sig);
+			}
+			//signal is held until the thread is resumed, unless the policy says to swallow it
+			//SIGTRAP is ours (breakpoints, stepping), never passed on
+			if sig != Signal::SIGTRAP && self.signals.get(sig).pass {
+				self.threads.get(tid).unwrap().pending_sig = Some(sig);
+			}
+		}
+		true
+	}
+
+	//runs one instruction of a thread. The int3 of a breakpoint its sitting on is lifted while it does
+	//signals that turn up are dealt with the way continue would. Ones that dont stop go in with the next step
+	//None if the process is gone
+	fn single_step(&mut self, tid: Pid) -> Option<WaitStatus> {
+		loop {
+			let sig = match self.threads.get(tid) {
+				Some(thread) => thread.pending_sig.take(),
+				None => None,
+			};
+			//rip sits on a breakpoint after hitting it. Take the int3 out so the real instruction is what gets stepped
+			let rip = Debugger::get_reg(tid, "rip").unwrap_or(0) as usize;
+			let on_bp = self.bp_table.suspend(rip);
+			ptrace::step(tid, sig).ok();
+			let status = match wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+				Ok(status) if self.process_gone(&status) => {return None;},
+				Ok(status) => status,
+				Err(_) => {
+					self.lost_process();
+					return None;
+				},
+			};
+			//only once its known theres still a process to put it back into
+			if on_bp {
+				self.bp_table.resume(rip);
+			}
+			let sig = match status {
+				WaitStatus::Stopped(_, sig) => sig,
+				_ => {return Some(status);},
+			};
+			let thread = self.threads.get(tid).unwrap();
+			thread.stop_sig = Some(sig);
+			if sig == Signal::SIGTRAP {
+				return Some(status);
+			}
+			let policy = self.signals.get(sig);
+			if policy.pass {
+				thread.pending_sig = Some(sig);
+			}
+			if let Some(idx) = self.caught(|kind| kind.catches_signal(sig)) {
+				println!("Catchpoint {} (signal {})", idx, sig.as_str());
+				self.last_stop = Some((tid, StopReason::Catchpoint(idx)));
+				return Some(status);
+			}
+			if policy.stop {
+				self.report_signal(tid, sig);
+				self.last_stop = Some((tid, StopReason::Signal(sig)));
+				return Some(status);
+			}
+			if policy.print {
+				self.report_signal(tid, sig);
+			}
+		}
+	}
+
+	//si / ni. Stops early for a breakpoint or signal on the way. Either way, shows the instruction its stopped at
+	fn step_instructions(&mut self, count: usize, over: bool, prog: &ProgInfo) {
+		let tid = self.cur_tid();
+		self.pending.clear();
+		self.last_stop = None;
+		for _ in 0..count {
+			let regs = match self.target.get_regs(tid) {
+				Ok(regs) => regs,
+				Err(_) => {return;},
+			};
+			//a call is run until it returns to the instruction after it, with the stack back where it is now
+			if over {
+				if let Some(insn { len, is_call: true, .. }) = self.decode_at(tid, regs.rip as usize) {
+					if !self.run_until_return(tid, regs.rip as usize + len, regs.rsp, prog) {
+						if self.state == ProcState::Stopped {
+							self.show_instruction(tid, prog);
+						}
+						return;
+					}
+					continue;
+				}
+			}
+			match self.single_step(tid) {
+				Some(WaitStatus::Stopped(_, Signal::SIGTRAP)) => {},
+				//a signal. Already reported
+				Some(_) => {
+					self.refresh_thread_regs();
+					self.show_instruction(tid, prog);
+					return;
+				},
+				None => {return;},
+			};
+			let regs = match self.target.get_regs(tid) {
+				Ok(regs) => regs,
+				Err(_) => {return;},
+			};
+			if self.bp_table.inserted(regs.rip as usize) && self.bps_stop_at(tid, regs.rip as usize, regs, prog) {
+				self.refresh_thread_regs();
+				self.show_instruction(tid, prog);
+				return;
+			}
+		}
+		self.refresh_thread_regs();
+		self.last_stop = Some((tid, StopReason::Step));
+		self.show_instruction(tid, prog);
+	}
+
+	//the instruction at addr, as the program has it (not the int3's of any breakpoints there)
+	fn decode_at(&self, tid: Pid, addr: usize) -> Option<insn> {
+		let mut bytes = Vec::new();
+		for word in 0..2 {
+			match self.target.read_mem(tid, addr + word*8) {
+				Ok(val) => {bytes.extend_from_slice(&val.to_le_bytes());},
+				//end of the mapping. What was read might still be enough
+				Err(_) => {break;},
+			};
+		}
+		bytes.truncate(MAX_INSN_LEN);
+		//int3s are shown as what they replaced
+		for (i, byte) in bytes.iter_mut().enumerate() {
+			match self.bp_table.sites.get(&(addr + i)) {
+				Some(site) if site.bp.enabled => {*byte = site.bp.saved_byte();},
+				_ => {},
+			};
+		}
+		decode(&bytes, addr as u64)
+	}
+
+	//rip, the symbol+offset its at, and the instruction there
+	fn show_instruction(&self, tid: Pid, prog: &ProgInfo) {
+		let rip = match Debugger::get_reg(tid, "rip") {
+			Ok(rip) => rip,
+			Err(_) => {return;},
+		};
+		let text = match self.decode_at(tid, rip as usize) {
+			Some(insn) => insn.text,
+			None => String::from("(bad)"),
+		};
+		println!("{:#x} ({}):\t{}", rip, prog.describe_addr(self.to_file(rip as usize) as u64), text);
+	}
+
+	//step / next. Runs the current thread until it gets to the start of another source line, or back out to the caller
+	//step goes into calls to functions that have line info. Anything else is run over, with the whole process running until it returns
+	fn step_line(&mut self, over: bool, prog: &ProgInfo) {
+		let tid = self.cur_tid();
+		let start = match Debugger::get_reg(tid, "rip").ok().and_then(|rip| prog.line_for_addr(self.to_file(rip as usize) as u64)) {
+			Some(row) => (row.file.clone(), row.line),
+			None => {
+				self.fail("No line info for the current location. Use si, or finish to get out of the function");
+				return;
+			},
+		};
+		self.pending.clear();
+		self.last_stop = None;
+		loop {
+			let before = match self.target.get_regs(tid) {
+				Ok(regs) => regs,
+				Err(_) => {return;},
+			};
+			//if the step is a ret, this is where it goes
+			let top = self.read_mem(before.rsp as usize).unwrap_or(0);
+			match self.single_step(tid) {
+				Some(WaitStatus::Stopped(_, Signal::SIGTRAP)) => {},
+				//a signal, or the process is gone. Already reported
+				Some(_) => {
+					self.refresh_thread_regs();
+					return;
+				},
+				None => {return;},
+			};
+			let regs = match self.target.get_regs(tid) {
+				Ok(regs) => regs,
+				Err(_) => {return;},
+			};
+			let pc = regs.rip as usize;
+			//a breakpoint on the way stops it, the same as if it had been continued to
+			if self.stepped_onto_bp(tid, prog) {
+				return;
+			}
+			let ret = self.read_mem(regs.rsp as usize).unwrap_or(0);
+			//a call pushes the address just past itself. A call to the very next instruction (to get rip) doesnt count
+			let called = regs.rsp == before.rsp.wrapping_sub(8) && ret > before.rip && ret <= before.rip + 15 && regs.rip != ret;
+			let returned = regs.rsp == before.rsp + 8 && regs.rip == top;
+			let mut pc = pc;
+			if called {
+				if !over && prog.line_for_addr(self.to_file(pc) as u64).is_some() {
+					self.step_to_body(tid, prog);
+					return;
+				}
+				if !self.run_until_return(tid, ret as usize, regs.rsp + 8, prog) {
+					return;
+				}
+				//the call can be the last thing on the line, so this might already be the next one
+				pc = ret as usize;
+			}
+			let row = prog.line_for_addr(self.to_file(pc) as u64);
+			let stop = match row {
+				//back out in the caller. Stops even in the middle of a line, so the rest of the callers line isnt skipped
+				_ if returned => row.is_none_or(|row| (row.file.clone(), row.line) != start),
+				//line 0 is code the compiler made up, that doesnt belong to any line
+				Some(row) => row.line != 0 && (row.file.clone(), row.line) != start && row.addr == self.to_file(pc) as u64,
+				None => true,
+			};
+			if stop {
+				self.refresh_thread_regs();
+				self.last_stop = Some((tid, StopReason::Step));
+				self.report_location(tid, prog);
+				return;
+			}
+		}
+	}
+
+	//a single step has put the thread on a breakpoint. Counts the hit, and stops (and says where) if any of the breakpoints there want to
+	fn stepped_onto_bp(&mut self, tid: Pid, prog: &ProgInfo) -> bool {
+		let regs = match self.target.get_regs(tid) {
+			Ok(regs) => regs,
+			Err(_) => {return false;},
+		};
+		let pc = regs.rip as usize;
+		if !self.bp_table.inserted(pc) || !self.bps_stop_at(tid, pc, regs, prog) {
+			return false;
+		}
+		self.refresh_thread_regs();
+		self.report_location(tid, prog);
+		true
+	}
+
+	//step has just gone into a function. Carries on through its prologue, so the arguments are where DWARF says they are
+	fn step_to_body(&mut self, tid: Pid, prog: &ProgInfo) {
+		let entry = Debugger::get_reg(tid, "rip").unwrap_or(0) as usize;
+		let body = match prog.symbol_for(self.to_file(entry) as u64) {
+			Some((sym, 0)) => Some((self.to_runtime(prog.after_prologue(sym) as usize), entry + sym.size as usize)),
+			_ => None,
+		};
+		if let Some((body, end)) = body {
+			loop {
+				let pc = Debugger::get_reg(tid, "rip").unwrap_or(0) as usize;
+				if pc == body || pc < entry || pc >= end {
+					break;
+				}
+				match self.single_step(tid) {
+					Some(WaitStatus::Stopped(_, Signal::SIGTRAP)) => {},
+					Some(_) => {
+						self.refresh_thread_regs();
+						return;
+					},
+					None => {return;},
+				};
+				//a breakpoint in the prologue, or on the first line of the body (break <function>), is hit the same as step_line would
+				if self.stepped_onto_bp(tid, prog) {
+					return;
+				}
+			}
+		}
+		self.refresh_thread_regs();
+		self.last_stop = Some((tid, StopReason::Step));
+		self.report_location(tid, prog);
+	}
+
+	//finish. Runs until the current function returns, then shows what it returned
+	fn finish(&mut self, prog: &ProgInfo) {
+		let tid = self.cur_tid();
+		let regs = match self.target.get_regs(tid) {
+			Ok(regs) => regs,
+			Err(err_num) => {
+				self.fail(&format!("Failed to retrieve registers with ptrace.\n Error code was {}", err_num));
+				return;
+			},
+		};
+		let pc = self.to_file(regs.rip as usize) as u64;
+		//CFA is the stack pointer from before the call, so the return address is just under it
+		let cfa = match prog.frames.as_ref().and_then(|frames| frames.cfa(pc, &regs_to_dict(regs))) {
+			Some(cfa) => cfa,
+			None => match prog.symbol_for(pc) {
+				//nothing has been pushed yet
+				Some((_, 0)) => regs.rsp + 8,
+				//past the prologue. Only right if it keeps a frame pointer
+				Some(_) => regs.rbp + 16,
+				None => {
+					self.fail("Cannot tell where the current function returns to. Theres no unwind info for it");
+					return;
+				},
+			},
+		};
+		let ret = match self.read_mem((cfa - 8) as usize) {
+			Ok(ret) => ret as usize,
+			Err(err_num) => {
+				self.fail(&format!("Failed to read the return address at {:#x}. Error was {}", cfa - 8, err_num));
+				return;
+			},
+		};
+		println!("Run till exit from {:#x} ({})", regs.rip, prog.describe_addr(pc));
+		let kind = return_type(&prog.dwarf, pc);
+		if !self.run_until_return(tid, ret, cfa, prog) {
+			return;
+		}
+		self.report_location(tid, prog);
+		self.print_return_value(tid, kind);
+	}
+
+	//lets the process run until tid gets back to ret, with its stack pointer at sp or above
+	//true if it got there. Otherwise something else stopped it, and has already been reported
+	fn run_until_return(&mut self, tid: Pid, ret: usize, sp: u64, prog: &ProgInfo) -> bool {
+		if let Err(err_num) = self.bp_table.add_internal(ret) {
+			self.fail(&format!("Failed to set a breakpoint at the return address {:#x}. Error was {}", ret, err_num));
+			return false;
+		}
+		if let Some(thread) = self.threads.get(tid) {
+			thread.stop_sig = Some(Signal::SIGTRAP);
+		}
+		self.returning = Some((tid, ret, sp));
+		self.continue_exec(prog);
+		self.returning = None;
+		if self.state != ProcState::Stopped {
+			return false;
+		}
+		self.bp_table.remove_internal(ret);
+		match self.last_stop {
+			Some((stop_tid, StopReason::Step)) => stop_tid == tid,
+			_ => false,
+		}
+	}
+
+	//where a thread has stepped to, with the source line if the file can be found
+	fn report_location(&self, tid: Pid, prog: &ProgInfo) {
+		let rip = match Debugger::get_reg(tid, "rip") {
+			Ok(rip) => rip,
+			Err(_) => {return;},
+		};
+		let pc = self.to_file(rip as usize) as u64;
+		println!("{:#x} ({})", rip, prog.describe_addr(pc));
+		if let Some(row) = prog.line_for_addr(pc) {
+			if let Some(text) = prog.source_line(&row.file, row.line) {
+				println!("{}\t{}", row.line, text);
+			}
+		}
+	}
+
+	//what finish shows. Integers and pointers come back in rax, floats and doubles in xmm0
+	fn print_return_value(&self, tid: Pid, kind: Option<ret_type>) {
+		let rax = Debugger::get_reg(tid, "rax").unwrap_or(0);
+		let xmm0 = get_xmm0(tid).unwrap_or(0);
+		match kind {
+			Some(ret_type::Void) => {},
+			Some(ret_type::Int(size, true)) => {println!("Value returned: {} (rax)", truncate(rax, int_type { size: size as u32, signed: true }));},
+			Some(ret_type::Int(size, false)) => {println!("Value returned: {} (rax)", truncate(rax, int_type { size: size as u32, signed: false }) as u64);},
+			Some(ret_type::Float(4)) => {println!("Value returned: {} (xmm0)", f32::from_bits(xmm0 as u32));},
+			Some(ret_type::Float(_)) => {println!("Value returned: {} (xmm0)", f64::from_bits(xmm0));},
+			Some(ret_type::Pointer) => {println!("Value returned: {:#x} (rax)", rax);},
+			Some(ret_type::Other) => {println!("Value returned isnt an integer, pointer or float. rax = {:#x}", rax);},
+			None => {println!("No debug info for the function. rax = {:#x}, xmm0 = {} (as a double)", rax, f64::from_bits(xmm0));},
+		};
+	}
+
+	//a thread is on its way into or out of a syscall. Returns whether a catchpoint wants to stop for it
+	fn syscall_stop(&mut self, tid: Pid) -> bool {
+		let regs = match ptrace::getregs(tid) {
+			Ok(regs) => regs,
+			Err(_) => {return true;},
+		};
+		//rax gets the return value, orig_rax keeps the number for the whole syscall
+		let nr = regs.orig_rax;
+		let idx = match self.caught(|kind| kind.catches_syscall(nr)) {
+			Some(idx) => idx,
+			None => {return false;},
+		};
+		//older kernels dont have PTRACE_GET_SYSCALL_INFO. rax is -ENOSYS on the way in
+		let entering = syscall_entering(tid).unwrap_or(regs.rax as i64 == -(libc::ENOSYS as i64));
+		if entering {
+			let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+			let read = |addr: u64| self.target.read_mem(tid, addr as usize).ok();
+			println!("Catchpoint {} (call to syscall {}), {}", idx, syscall_name(nr), format_call(nr, args, &read));
+		}
+		else {
+			println!("Catchpoint {} (returned from syscall {}) = {}", idx, syscall_name(nr), format_return(nr, regs.rax));
+		}
+		println!("  rip: {:#x}", regs.rip);
+		self.last_stop = Some((tid, StopReason::Catchpoint(idx)));
+		true
+	}
+
+	//first catchpoint the test is true for
+	fn caught<F: Fn(&CatchKind) -> bool>(&self, test: F) -> Option<usize> {
+		self.catches.iter().position(|catch_opt| catch_opt.as_ref().is_some_and(&test))
+	}
+
+	//syscall stops only happen if the threads are resumed with PTRACE_SYSCALL
+	fn catching_syscalls(&self) -> bool {
+		self.catches.iter().flatten().any(|kind| matches!(kind, CatchKind::Syscall(_)))
+	}
+
+	//catch syscall|signal|fork|exec|exit ...
+	//catch list | l
+	//catch delete | de <idx>
+	fn handle_catch(&mut self, args: Vec<&str>) {
+		match args.get(1) {
+			Some(&"list") | Some(&"l") => {
+				println!("<idx>: <event>");
+				for (idx, catch_opt) in self.catches.iter().enumerate() {
+					if let Some(kind) = catch_opt {
+						println!("<{}>: <{}>", idx, kind.describe());
+					}
+				}
+			},
+			Some(&"delete") | Some(&"de") => {
+				let idx = match args.get(2).and_then(|idx| str_to_int(idx)) {
+					Some(idx) => idx,
+					None => {
+						self.fail("Need index of catchpoint to delete");
+						return;
+					},
+				};
+				match self.catches.get_mut(idx) {
+					Some(catch_opt) if catch_opt.is_some() => {
+						*catch_opt = None;
+					},
+					_ => {
+						self.fail("Error deleting catchpoint. Invalid index.");
+						return;
+					},
+				};
+				self.set_trace_options();
+			},
+			_ => {
+				let kind = match parse_catch(&args[1..]) {
+					Ok(kind) => kind,
+					Err(err) => {
+						self.fail(&err);
+						return;
+					},
+				};
+				println!("Catchpoint {} ({})", self.catches.len(), kind.describe());
+				self.catches.push(Some(kind));
+				self.set_trace_options();
+			},
+		};
+	}
+
+	//nothing left to wait on. Process must have been reaped elsewhere
+	fn lost_process(&mut self) {
+		println!("Lost track of process {}", self.m_pid);
+		self.threads.threads.clear();
+		self.state = ProcState::Killed;
+	}
+
+	//software watchpoints. The selected thread is single stepped, and the watched values checked after every instruction
+	//other threads run as normal, so only changes made by the selected thread are caught
+	fn continue_watching(&mut self, prog: &ProgInfo) {
+		let tid = self.cur_tid();
+		let start_pc = Debugger::get_reg(tid, "rip").unwrap_or(0);
+		if !self.step_past_breakpoints(prog) {
+			return;
+		}
+		//stepping past a breakpoint runs an instruction too
+		let moved = Debugger::get_reg(tid, "rip").is_ok_and(|rip| rip != start_pc);
+		if moved && self.soft_watch_changed(tid, start_pc, prog) {
+			return;
+		}
+		//resume_all only touches stopped threads, so this leaves the selected one for stepping
+		if let Some(thread) = self.threads.get(tid) {
+			thread.state = ThreadState::Running;
+		}
+		self.resume_all();
+
+		loop {
+			let pc = match Debugger::get_reg(tid, "rip") {
+				Ok(rip) => rip,
+				Err(_) => {return;},
+			};
+			let sig = self.threads.get(tid).and_then(|thread| thread.pending_sig.take());
+			ptrace::step(tid, sig).ok();
+			let (stop_tid, status) = match self.wait_for_stop(prog) {
+				Some(stop) => stop,
+				None => {
+					self.lost_process();
+					return;
+				},
+			};
+			//just the step finishing. Anything else (int3 at pc, debug register, signal, another thread) is a normal stop
+			let on_bp = self.bp_table.inserted(pc as usize);
+			if stop_tid == tid && status == WaitStatus::Stopped(tid, Signal::SIGTRAP) && !on_bp && !self.hw.fired(tid) {
+				if self.soft_watch_changed(tid, pc, prog) {
+					return;
+				}
+				continue;
+			}
+			if self.stopped_with(stop_tid, status, prog) {
+				return;
+			}
+			//condition was false. Everything is stopped again, so step past the breakpoint and let the rest go
+			if !self.step_past_breakpoints(prog) {
+				return;
+			}
+			if self.soft_watch_changed(tid, pc, prog) {
+				return;
+			}
+			if let Some(thread) = self.threads.get(tid) {
+				thread.state = ThreadState::Running;
+			}
+			self.resume_all();
+		}
+	}
+
+	//checks every software watchpoint after the selected thread has run the instruction at pc
+	//if any changed, stops the rest of the process and reports it
+	fn soft_watch_changed(&mut self, tid: Pid, pc: u64, prog: &ProgInfo) -> bool {
+		let mut changed = Vec::new();
+		for idx in 0..self.soft_watches.len() {
+			let target = self.soft_watches[idx].target.clone();
+			let new_value = self.watched_value(tid, &target, prog);
+			let watch = &mut self.soft_watches[idx];
+			//couldnt be read (e.g variable out of scope). Wait until it can be again
+			//coming into scope just gives it a starting value
+			let new_value = match new_value {
+				Some(new_value) if new_value != *watch.old_value.as_ref().unwrap_or(&new_value) => new_value,
+				Some(new_value) => {
+					watch.old_value = Some(new_value);
+					continue;
+				},
+				None => {continue;},
+			};
+			changed.push((idx, watch.old_value.replace(new_value.clone()).unwrap(), new_value));
+		}
+		if changed.is_empty() {
+			return false;
+		}
+
+		if let Some(thread) = self.threads.get(tid) {
+			thread.state = ThreadState::Stopped;
+			//a normal step. Not sitting past a breakpoint
+			thread.stop_sig = None;
+		}
+		self.threads.selected = tid;
+		self.stop_all_threads(prog);
+		self.refresh_thread_regs();
+		self.last_stop = Some((tid, StopReason::Watchpoint(self.soft_watches[changed[0].0].id)));
+		for (idx, old_value, new_value) in changed {
+			let watch = &self.soft_watches[idx];
+			println!("Software watchpoint {}: {}", watch.id, watch.describe());
+			println!("Old value = {}", format_watched(&old_value));
+			println!("New value = {}", format_watched(&new_value));
+		}
+		println!("Changed by the instruction at {:#x} ({})", pc, prog.describe_addr(self.to_file(pc as usize) as u64));
+		if let Ok(rip) = Debugger::get_reg(tid, "rip") {
+			println!("  rip: {:#x}", rip);
+		}
+		true
+	}
+
+	//current value of what a software watchpoint looks at, as bytes
+	fn watched_value(&self, tid: Pid, target: &WatchTarget, prog: &ProgInfo) -> Option<Vec<u8>> {
+		match target {
+			WatchTarget::Region(addr, len) => read_bytes(tid, *addr, *len),
+			WatchTarget::Expr(text) => {
+				let regs = self.target.get_regs(tid).ok()?;
+				let env = stop_env { dbg: self, prog, tid, regs: regs_to_dict(regs) };
+				let val = parse_expr(text).and_then(|parsed| eval(&parsed, &env)).ok()?;
+				Some(val.to_le_bytes().to_vec())
+			},
+		}
+	}
+
+	//a debug register went off. Prints what happened and returns whether to stay stopped
+	//watch only stops if the value changed, and rwatch only if it didnt (a write looks the same as a read to the cpu)
+	fn watchpoint_hit(&mut self, tid: Pid, idx: usize, prog: &ProgInfo) -> bool {
+		let wp = match &mut self.hw.slots[idx] {
+			Some(wp) => wp,
+			//deleted since the trap was raised. Nothing to report, and the trap is still ours
+			None => {return false;},
+		};
+		let id = wp.id;
+		if wp.kind == WatchKind::Exec {
+			let addr = wp.addr;
+			println!("Hardware breakpoint {} at {:#x} ({})", id, addr, prog.describe_addr(self.to_file(addr) as u64));
+			self.last_stop = Some((tid, StopReason::Watchpoint(id)));
+			return true;
+		}
+		let new_value = match self.target.read_mem(tid, wp.addr) {
+			Ok(val) => mask_value(val, wp.len),
+			Err(_) => wp.old_value,
+		};
+		let old_value = wp.old_value;
+		wp.old_value = new_value;
+		let changed = new_value != old_value;
+		match wp.kind {
+			WatchKind::Write if !changed => {return false;},
+			WatchKind::Read if changed => {return false;},
+			_ => {},
+		};
+		let kind = match wp.kind {
+			WatchKind::Write => "Hardware watchpoint",
+			WatchKind::Read => "Hardware read watchpoint",
+			_ => "Hardware access (read/write) watchpoint",
+		};
+		println!("{} {}: {:#x}", kind, id, wp.addr);
+		if changed {
+			println!("Old value = {:#x}", old_value);
+			println!("New value = {:#x}", new_value);
+		}
+		else {
+			println!("Value = {:#x}", new_value);
+		}
+		self.last_stop = Some((tid, StopReason::Watchpoint(id)));
+		true
+	}
+
+	//puts the hardware breakpoints and watchpoints into every thread. They all have to be stopped
+	fn apply_hw(&mut self) {
+		for tid in self.threads.tids() {
+			if let Err(err_num) = self.hw.apply(tid) {
+				self.fail(&format!("Failed to set debug registers of thread {}. Error was {}", tid, err_num));
+				return;
+			}
+		}
+	}
+
+	//hbreak <loc>
+	//watch | rwatch | awatch <addr|symbol> [len]
+	//watch -s <addr|symbol> [len]
+	//watch -s <expression>
+	//watch list
+	//watch delete <idx>
+	fn handle_watch(&mut self, args: Vec<&str>, prog: &ProgInfo) {
+		if args.len() < 2 {
+			self.fail("Need an address to watch");
+			return;
+		}
+		let kind = match args[0] {
+			"hbreak" => WatchKind::Exec,
+			"rwatch" => WatchKind::Read,
+			"awatch" => WatchKind::Access,
+			_ => WatchKind::Write,
+		};
+		if kind == WatchKind::Write {
+			match args[1] {
+				"list" | "l" => {
+					self.list_watchpoints(prog);
+					return;
+				},
+				"-s" => {
+					self.create_soft_watch(args, prog);
+					return;
+				},
+				"delete" | "de" => {
+					let removed = match args.get(2).and_then(|id| str_to_int(id)) {
+						Some(id) => self.delete_watch(id),
+						None => Err(()),
+					};
+					if removed.is_err() {
+						self.fail("Error deleting watchpoint. Invalid id.");
+					}
+					return;
+				},
+				_ => {},
+			};
+		}
+
+		let (addr, len) = if kind == WatchKind::Exec {
+			match self.resolve_location(args[1], prog) {
+				Ok(addr) => (addr, 1),
+				Err(err) => {
+					self.fail(&err);
+					return;
+				},
+			}
+		}
+		else {
+			let (addr, size) = match self.resolve_data(args[1], prog) {
+				Ok(found) => found,
+				Err(err) => {
+					self.fail(&err);
+					return;
+				},
+			};
+			let len = match args.get(2) {
+				Some(len) => match str_to_int(len) {
+					Some(len) => len,
+					None => {
+						self.fail("Invalid length");
+						return;
+					},
+				},
+				None => size,
+			};
+			(addr, len)
+		};
+
+		let old_value = match self.read_mem(addr) {
+			Ok(val) => mask_value(val, len),
+			Err(err_num) => {
+				self.fail(&format!("Cannot access memory at {:#x}. Error was {}", addr, err_num));
+				return;
+			},
+		};
+		//only used up once its known theres a debug register for it
+		let id = self.bp_table.next_id;
+		if let Err(err) = self.hw.insert(watchpoint { id, addr, len, kind, old_value }) {
+			self.fail(&err);
+			return;
+		}
+		self.bp_table.new_id();
+		self.apply_hw();
+		match kind {
+			WatchKind::Exec => println!("Hardware breakpoint {} at {:#x} ({})", id, addr, prog.describe_addr(self.to_file(addr) as u64)),
+			_ => println!("Hardware {} {}: {:#x} ({} bytes)", kind.name(), id, addr, len),
+		};
+	}
+
+	fn is_watch(&self, id: usize) -> bool {
+		self.hw.slot_of(id).is_some() || self.soft_watches.iter().any(|watch| watch.id == id)
+	}
+
+	//watch delete, and b delete of a watchpoints id. Hardware or software
+	fn delete_watch(&mut self, id: usize) -> Result<(),()> {
+		if self.hw.remove(id).is_ok() {
+			self.apply_hw();
+			return Ok(());
+		}
+		match self.soft_watches.iter().position(|watch| watch.id == id) {
+			Some(idx) => {
+				self.soft_watches.remove(idx);
+				Ok(())
+			},
+			None => Err(()),
+		}
+	}
+
+	//address and size of something to watch. An address (8 bytes), or a data symbol
+	fn resolve_data(&self, loc: &str, prog: &ProgInfo) -> Result<(usize, usize), String> {
+		let loc = loc.strip_prefix('&').unwrap_or(loc);
+		if let Some(addr) = self.parse_code_addr(loc) {
+			return Ok((addr, 8));
+		}
+		match prog.lookup_symbol(loc) {
+			Some(sym) => Ok((self.to_runtime(sym.addr as usize), (sym.size as usize).clamp(1, 8))),
+			None => Err(format!("No symbol {} found", loc)),
+		}
+	}
+
+	//watch -s <addr|symbol> [len], or watch -s <expression>
+	fn create_soft_watch(&mut self, args: Vec<&str>, prog: &ProgInfo) {
+		if args.len() < 3 {
+			self.fail("Need an address or expression to watch");
+			return;
+		}
+		//a single address or symbol is a block of memory. Anything else is an expression (e.g a local variable)
+		let region = match self.resolve_data(args[2], prog) {
+			Ok((addr, size)) if args.len() <= 4 => {
+				match args.get(3).map(|len| str_to_int(len)) {
+					Some(Some(len)) if len > 0 => Some((addr, len)),
+					Some(_) => {
+						self.fail("Invalid length");
+						return;
+					},
+					None => Some((addr, size)),
+				}
+			},
+			_ => None,
+		};
+		let target = match region {
+			Some((addr, len)) => WatchTarget::Region(addr, len),
+			None => {
+				let text = args[2..].join(" ");
+				if let Err(err) = parse_expr(&text) {
+					self.fail(&err);
+					return;
+				}
+				WatchTarget::Expr(text)
+			},
+		};
+		let tid = self.cur_tid();
+		let old_value = self.watched_value(tid, &target, prog);
+		if old_value.is_none() {
+			if let WatchTarget::Region(addr, _) = target {
+				self.fail(&format!("Cannot access memory at {:#x}", addr));
+				return;
+			}
+			println!("Expression cant be worked out yet. Watching for when it can be");
+		}
+		let watch = soft_watch { id: self.bp_table.new_id(), target, old_value };
+		println!("Software watchpoint {}: {}", watch.id, watch.describe());
+		self.soft_watches.push(watch);
+	}
+
+	fn list_watchpoints(&self, prog: &ProgInfo) {
+		println!("<id>: <type>: <addr>: <len>: <location>");
+		//in the order they were made, whichever debug register they ended up in
+		let mut hw : Vec<&watchpoint> = self.hw.slots.iter().flatten().collect();
+		hw.sort_by_key(|wp| wp.id);
+		for wp in hw {
+			println!("<{}>: <{}>: <{:#x}>: <{}>: <{}>", wp.id, wp.kind.name(), wp.addr, wp.len, prog.describe_addr(self.to_file(wp.addr) as u64));
+		}
+		for watch in &self.soft_watches {
+			match &watch.target {
+				WatchTarget::Region(addr, len) => {
+					println!("<{}>: <sw watchpoint>: <{:#x}>: <{}>: <{}>", watch.id, addr, len, prog.describe_addr(self.to_file(*addr) as u64));
+				},
+				WatchTarget::Expr(text) => {
+					println!("<{}>: <sw watchpoint>: <{}>", watch.id, text);
+				},
+			};
+		}
+	}
+
+	//a thread stopped with SIGTRAP. If its on one of our int3s, counts the hits and returns whether it should stay stopped
+	//every enabled breakpoint at the address gets a look. It stays running only if none of them want to stop
+	//(conditions all false, or hits being ignored)
+	//a condition that cant be worked out stops the process, so the user can fix it
+	//either way rip is moved back onto the breakpoint. step_over_breakpoint gets it past when its resumed
+	fn breakpoint_hit(&mut self, tid: Pid, prog: &ProgInfo) -> bool {
+		let mut regs = match self.target.get_regs(tid) {
+			Ok(regs) => regs,
+			Err(_) => {return true;},
+		};
+		//int3 has already run
+		let addr = regs.rip.wrapping_sub(1);
+		if !self.bp_table.inserted(addr as usize) {
+			return true;
+		}
+		regs.rip = addr;
+		Debugger::set_reg(tid, "rip", addr).ok();
+		self.refresh_thread_regs();
+
+		//next/finish got back out of the call. Checked against the stack, as a deeper call of the same function returns to the same place
+		let mut returned = false;
+		if let Some((step_tid, ret, sp)) = self.returning {
+			if tid == step_tid && addr as usize == ret && regs.rsp >= sp {
+				self.last_stop = Some((tid, StopReason::Step));
+				returned = true;
+			}
+		}
+		let stop = self.bps_stop_at(tid, addr as usize, regs, prog);
+		stop || returned
+	}
+
+	//a thread is on addr, where there are breakpoints. Whether any of them want to stop (each one that does is reported)
+	fn bps_stop_at(&mut self, tid: Pid, addr: usize, regs: user_regs_struct, prog: &ProgInfo) -> bool {
+		let mut stop = false;
+		self.pending.clear();
+		for id in self.bp_table.ids_at(addr).to_vec() {
+			if self.bp_should_stop(tid, id, regs, prog) {
+				stop = true;
+			}
+		}
+		stop
+	}
+
+	//one of the breakpoints at the address a thread stopped on. Reports it, and queues up its commands, if it wants to stop
+	fn bp_should_stop(&mut self, tid: Pid, id: usize, regs: user_regs_struct, prog: &ProgInfo) -> bool {
+		let (enabled, condition) = match self.bp_table.get(id) {
+			Some(bp) => (bp.enabled, bp.condition.clone()),
+			None => {return false;},
+		};
+		if !enabled {
+			return false;
+		}
+		if let Some(condition) = condition {
+			let env = stop_env { dbg: self, prog, tid, regs: regs_to_dict(regs) };
+			match parse_expr(&condition).and_then(|parsed| eval(&parsed, &env)) {
+				Ok(0) => {return false;},
+				Ok(_) => {},
+				Err(err) => {
+					println!("Error in condition \"{}\" of breakpoint {}: {}", condition, id, err);
+					self.report_breakpoint(tid, id, prog);
+					return true;
+				},
+			};
+		}
+
+		let bp = self.bp_table.get_mut(id).unwrap();
+		bp.hits += 1;
+		if bp.ignore_count > 0 {
+			bp.ignore_count -= 1;
+			return false;
+		}
+		let disposition = bp.disposition;
+		let dprintf = bp.dprintf.clone();
+		match &dprintf {
+			Some(fmt) => {
+				let env = stop_env { dbg: self, prog, tid, regs: regs_to_dict(regs) };
+				let text = fmt.render(&env).unwrap_or_else(|err| format!("<dprintf {}: {}>\n", id, err));
+				self.dprintf_output(&text);
+			},
+			None => {
+				self.pending.extend(bp.commands.iter().cloned());
+				self.report_breakpoint(tid, id, prog);
+			},
+		};
+		match disposition {
+			Disposition::Keep => {},
+			Disposition::Delete => {
+				self.bp_table.delete(id).ok();
+				println!("Temporary breakpoint {} deleted", id);
 			},
-			"restore" => {
-				self.restore_trace_entrance(args);
+			Disposition::Disable => {
+				self.bp_table.disable(id).ok();
+				self.bp_table.get_mut(id).unwrap().disposition = Disposition::Keep;
 			},
-			_ => {println!("Invalid command");},
 		};
-		dbg_result
+		dprintf.is_none()
 	}
 
-	fn restore_trace_entrance(&mut self, args: Vec<&str>) {
-		if args.len() < 2 {
-			println!("File must be specified");
+	//a dprintf hit. To the log file if there is one
+	fn dprintf_output(&mut self, text: &str) {
+		if let Some((path, file)) = &mut self.dprintf_log {
+			if file.write_all(text.as_bytes()).is_ok() {
+				return;
+			}
+			println!("Failed to write to dprintf log {}. Going back to the console", path);
+			self.dprintf_log = None;
 		}
+		print!("{}", text);
+		std::io::stdout().flush().ok();
+	}
 
-		let mut file = match File::open(args[1]) {
-			Ok(result) => result,
-			Err(err)=> {
-				println!("error in opening file. Error was {:?}", err.kind()); 
-				return;
+	fn open_dprintf_log(&mut self, path: &str) -> Result<(), String> {
+		match OpenOptions::new().create(true).append(true).open(path) {
+			Ok(file) => {
+				self.dprintf_log = Some((path.to_string(), file));
+				Ok(())
 			},
+			Err(err) => Err(format!("Failed to open dprintf log {}. Error was {}", path, err)),
+		}
+	}
+
+	//Breakpoint <id> at <addr> (<symbol> file:line)
+	//with several at the same address, the first one reported is the stop reason
+	fn report_breakpoint(&mut self, tid: Pid, id: usize, prog: &ProgInfo) {
+		let (addr, disposition) = match self.bp_table.get(id) {
+			Some(bp) => (bp.addr, bp.disposition),
+			None => {return;},
+		};
+		let kind = if disposition == Disposition::Delete {"Temporary breakpoint"} else {"Breakpoint"};
+		println!("{} {} at {:#x} ({})", kind, id, addr, self.describe_bp(id, addr, prog));
+		match self.last_stop {
+			Some((_, StopReason::Breakpoint(_, _))) => {},
+			_ => {self.last_stop = Some((tid, StopReason::Breakpoint(id, addr)));},
 		};
+	}
 
-		Trace::restore(&mut file, self);
+	//if the thread is stopped on a breakpoint it has hit, put the original instruction back and run it
+	//then rearm the breakpoint. Returns false if the step ended some other way (see step_past_breakpoints)
+	fn step_over_breakpoint(&mut self, tid: Pid, prog: &ProgInfo) -> bool {
+		//stopped for some other reason (e.g we stopped it), so it hasnt hit anything
+		match self.threads.get(tid) {
+			Some(thread) if thread.stop_sig == Some(Signal::SIGTRAP) => {},
+			_ => {return true;},
+		};
+		//get value of pc. breakpoint_hit has already moved it back onto the breakpoint
+		let addr = match Debugger::get_reg(tid, "rip") {
+			Ok(rip) => rip as usize,
+			Err(_) => {return true;},
+		};
+		//hardware breakpoints fire before the instruction runs, so rip is still on it
+		//the debug register is turned off for this thread while it steps past
+		let hw_hit = self.hw.slots.iter().position(|slot| match slot {
+			Some(wp) => wp.kind == WatchKind::Exec && wp.addr == addr,
+			None => false,
+		});
+		//no int3 there (or its disabled). Nothing in the way
+		if hw_hit.is_none() && !self.bp_table.inserted(addr) {
+			return true;
+		}
+		if let Some(idx) = hw_hit {
+			self.hw.suspend(tid, idx).ok();
+		}
+		//the other threads are all stopped, so none of them can run past the int3 while its out
+		//single_step puts it back, and deals with any signal that turns up the way continue would
+		let status = self.single_step(tid);
+		if hw_hit.is_some() && self.threads.contains(tid) {
+			self.hw.apply(tid).ok();
+		}
+		match status {
+			//the instruction can set off a watchpoint on its way past
+			Some(status @ WaitStatus::Stopped(_, Signal::SIGTRAP)) if self.hw.fired(tid) => !self.stopped_with(tid, status, prog),
+			Some(WaitStatus::Stopped(_, Signal::SIGTRAP)) => true,
+			//process is gone. Already reported
+			None => false,
+			//a signal that stops, or a catchpoint. single_step has said which
+			Some(WaitStatus::Stopped(_, _)) => {
+				self.threads.selected = tid;
+				self.refresh_thread_regs();
+				false
+			},
+			Some(status) => !self.stopped_with(tid, status, prog),
+		}
 	}
 
+	//gets every stopped thread that is sitting on a breakpoint past it, before they are all resumed
+	//false if one of them stopped for something else on the way. Thats been handled like any other stop
+	fn step_past_breakpoints(&mut self, prog: &ProgInfo) -> bool {
+		for tid in self.threads.tids() {
+			if !self.step_over_breakpoint(tid, prog) {
+				return false;
+			}
+		}
+		true
+	}
 
-	fn continue_exec(&mut self) {
-		//get value of pc
-		let addr =  (Debugger::get_reg(self.m_pid, "rip").unwrap() - 1) as usize;
-		
-		//check if there is a breakpoint at that addr
-		if self.bp_table.addr_list.contains(&addr) {
-			//cant search for it smartly so just check every breakpoint (sadly)
-			for bp_opt in &mut self.bp_table.bp_list {
-				let mut bp;
-				match bp_opt {
-					None => {
+	//sets every stopped thread running. Any signal that was caught while stopping it is passed back on
+	fn resume_all(&mut self) {
+		let syscalls = self.catching_syscalls();
+		for thread in self.threads.threads.values_mut() {
+			if thread.state != ThreadState::Stopped {
+				continue;
+			}
+			resume(thread.tid, thread.pending_sig.take(), syscalls);
+			thread.state = ThreadState::Running;
+			thread.stop_sig = None;
+			thread.regs = None;
+		}
+	}
+
+	//waits on all threads until one stops for a reason the user should know about
+	//new threads and thread exits are dealt with here and never returned
+	//returns None if there is nothing left to wait on
+	fn wait_for_stop(&mut self, prog: &ProgInfo) -> Option<(Pid, WaitStatus)> {
+		let syscalls = self.catching_syscalls();
+		loop {
+			let status = match wait::waitpid(None, Some(WaitPidFlag::__WALL)) {
+				Ok(status) => status,
+				Err(_) => {return None;},
+			};
+
+			match status {
+				WaitStatus::PtraceEvent(tid, _, event) if self.handle_side_event(tid, event) => {
+					resume(tid, None, syscalls);
+				},
+				WaitStatus::PtraceEvent(tid, _, event) if event == Event::PTRACE_EVENT_FORK as i32 || event == Event::PTRACE_EVENT_VFORK as i32 => {
+					let vfork = event == Event::PTRACE_EVENT_VFORK as i32;
+					let child = match self.take_fork_child(tid) {
+						Some(child) => child,
+						None => {
+							resume(tid, None, syscalls);
+							continue;
+						},
+					};
+					let carry_on = self.handle_fork(tid, child, vfork, prog);
+					if let Some(idx) = self.caught(|kind| *kind == CatchKind::Fork) {
+						println!("Catchpoint {} ({} process {})", idx, if vfork {"vforked"} else {"forked"}, child);
+						self.last_stop = Some((carry_on, StopReason::Catchpoint(idx)));
+						return Some((carry_on, status));
+					}
+					resume(carry_on, None, syscalls);
+				},
+				WaitStatus::PtraceEvent(_, _, event) if event == Event::PTRACE_EVENT_EXEC as i32 => {
+					//reported by the leader, whichever thread actually called exec
+					self.handle_exec(prog);
+					if let Some(idx) = self.caught(|kind| *kind == CatchKind::Exec) {
+						println!("Catchpoint {} (exec'd {})", idx, self.exec_prog.clone().unwrap_or_default());
+						self.last_stop = Some((self.m_pid, StopReason::Catchpoint(idx)));
+						return Some((self.m_pid, status));
+					}
+					resume(self.m_pid, None, syscalls);
+				},
+				//only turns up with catch exit. The leader going is the process going, other threads can go quietly
+				WaitStatus::PtraceEvent(tid, _, event) if event == Event::PTRACE_EVENT_EXIT as i32 => {
+					if tid == self.m_pid {
+						if let Some(idx) = self.caught(|kind| *kind == CatchKind::Exit) {
+							let code = ptrace::getevent(tid).map(|status| (status >> 8) & 0xff).unwrap_or(0);
+							println!("Catchpoint {} (exit), process {} about to exit with code {}", idx, tid, code);
+							self.last_stop = Some((tid, StopReason::Catchpoint(idx)));
+							return Some((tid, status));
+						}
+					}
+					resume(tid, None, syscalls);
+				},
+				WaitStatus::Stopped(tid, Signal::SIGSTOP) if self.is_starting(tid) => {
+					//a forked child can stop before its parent reports the fork. Not one of our threads
+					if tgid_of(tid) != Some(self.m_pid) {
+						self.early_children.push(tid);
 						continue;
+					}
+					//initial stop of a new thread. Its traced automatically, so just let it go
+					if !self.threads.contains(tid) {
+						let num = self.threads.add(tid, ThreadState::Running);
+						println!("New thread {} (tid {})", num, tid);
+					}
+					self.threads.get(tid).unwrap().state = ThreadState::Running;
+					//new threads start without any debug registers set
+					self.hw.apply(tid).ok();
+					resume(tid, None, syscalls);
+				},
+				WaitStatus::Exited(tid, _) | WaitStatus::Signaled(tid, _, _) => {
+					self.threads.remove(tid);
+					//the leader is only reaped once all others are gone, so this is the whole process
+					if tid == self.m_pid || self.threads.threads.is_empty() {
+						return Some((tid, status));
+					}
+				},
+				//signal the user has said not to stop for. Pass it straight on (or not)
+				//catch signal stops anyway
+				WaitStatus::Stopped(tid, sig) if sig != Signal::SIGTRAP && !self.signals.get(sig).stop && self.caught(|kind| kind.catches_signal(sig)).is_none() => {
+					let policy = self.signals.get(sig);
+					if policy.print {
+						self.report_signal(tid, sig);
+					}
+					resume(tid, if policy.pass {Some(sig)} else {None}, syscalls);
+				},
+				WaitStatus::Stopped(tid, _) | WaitStatus::PtraceEvent(tid, _, _) | WaitStatus::PtraceSyscall(tid) => {
+					return Some((tid, status));
+				},
+				_ => {},
+			};
+		}
+	}
+
+	//prints which thread got which signal, the siginfo details and where it was
+	fn report_signal(&mut self, tid: Pid, sig: Signal) {
+		let num = match self.threads.get(tid) {
+			Some(thread) => thread.num,
+			None => 0,
+		};
+		println!("Thread {} (tid {}) received signal {}, {}.", num, tid, sig.as_str(), describe(sig));
+		if let Ok(info) = ptrace::getsiginfo(tid) {
+			println!("  {}", describe_siginfo(sig, &info));
+		}
+		if let Ok(rip) = Debugger::get_reg(tid, "rip") {
+			println!("  rip: {:#x}", rip);
+		}
+	}
+
+	//whether a SIGSTOP from this thread is its first stop after being created
+	fn is_starting(&mut self, tid: Pid) -> bool {
+		match self.threads.get(tid) {
+			Some(thread) => thread.state == ThreadState::Starting,
+			//not even seen the clone event for it yet
+			None => true,
+		}
+	}
+
+	//all-stop: once one thread stops, every other thread is stopped too
+	fn stop_all_threads(&mut self, prog: &ProgInfo) {
+		for tid in self.threads.tids() {
+			let state = match self.threads.get(tid) {
+				Some(thread) => thread.state,
+				None => {continue;},
+			};
+			if state == ThreadState::Stopped {
+				continue;
+			}
+			//starting threads already have a SIGSTOP on the way
+			if state == ThreadState::Running {
+				unsafe { libc::syscall(libc::SYS_tgkill, self.m_pid.as_raw(), tid.as_raw(), libc::SIGSTOP); }
+			}
+
+			loop {
+				match wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+					Ok(WaitStatus::Stopped(_, Signal::SIGSTOP)) => {
+						if state == ThreadState::Starting {
+							self.hw.apply(tid).ok();
+						}
+						let thread = self.threads.get(tid).unwrap();
+						thread.state = ThreadState::Stopped;
+						thread.stop_sig = Some(Signal::SIGSTOP);
+						break;
 					},
-					Some(bp_tmp) => {
-						bp=bp_tmp;
+					//hit a breakpoint while we were stopping it. Move it back to the breakpoint
+					//so it hits it again when resumed, rather than reporting two stops at once
+					Ok(WaitStatus::Stopped(_, Signal::SIGTRAP)) => {
+						if let Ok(rip) = Debugger::get_reg(tid, "rip") {
+							if self.bp_table.inserted((rip - 1) as usize) {
+								Debugger::set_reg(tid, "rip", rip - 1).ok();
+							}
+						}
+						ptrace::cont(tid, None).ok();
 					},
-				}
-				//found the brekapoint at the address but its already disable. All good
-				if bp.addr == addr && bp.enabled == false {
-					break;
-				}
-				//found breakpooint and its enabed, so need to step around it
-				else if bp.addr == addr && bp.enabled == true {
-					bp.disable();
-					Debugger::set_reg(self.m_pid, "rip", addr as u64);
-					ptrace::step(self.m_pid, None);
-					wait::waitpid(self.m_pid, None);
-					bp.enable();
-					break;
-				}
+					//some other signal got in first. Hold onto it for when the thread is resumed
+					Ok(WaitStatus::Stopped(_, sig)) => {
+						self.threads.get(tid).unwrap().pending_sig = Some(sig);
+						ptrace::cont(tid, None).ok();
+					},
+					Ok(WaitStatus::PtraceEvent(_, _, event)) => {
+						//forking while being stopped. No good way to follow it from here, so let the child go
+						if event == Event::PTRACE_EVENT_FORK as i32 || event == Event::PTRACE_EVENT_VFORK as i32 {
+							if let Some(child) = self.take_fork_child(tid) {
+								self.bp_table.clone_for(child).remove_all();
+								ptrace::detach(child, None).ok();
+								println!("Detaching after fork from child process {}", child);
+							}
+						}
+						else if event == Event::PTRACE_EVENT_EXEC as i32 {
+							self.handle_exec(prog);
+						}
+						else {
+							self.handle_side_event(tid, event);
+						}
+						ptrace::cont(tid, None).ok();
+					},
+					Ok(WaitStatus::Exited(_, _)) | Ok(WaitStatus::Signaled(_, _, _)) | Err(_) => {
+						self.threads.remove(tid);
+						break;
+					},
+					Ok(_) => {
+						ptrace::cont(tid, None).ok();
+					},
+				};
 			}
 		}
+	}
 
-		if self.trace_state == TraceState::Tracing {
-			//need to loop because we dont want user to notice us breaking on syscalls
-			//so we speed by for each syscall, and only stop if signal generated is for something besides a syscall (probs a breakpoint)
-			loop {
-				ptrace::syscall(self.m_pid, None);
-				wait::waitpid(self.m_pid, None);
-				let siginfo = ptrace::getsiginfo(self.m_pid).unwrap();
-			
-				//if we are tracing, we need to get the signal info for each event
-				//For both breakpoints and stoped caused by SYSCALL, the signo is 5
-				//but for sysem the code is also 5. Have to check the signo to make sure its not something like a sigsegv signal or smn
-				if siginfo.si_signo == 5 && siginfo.si_code == 5 {
-							
-					ptrace::syscall(self.m_pid, None);
-					wait::waitpid(self.m_pid, None);
-					//println!("{:?}", wait::waitpid(self.m_pid, None).unwrap());
-						
-					let regs = regs_to_dict(ptrace::getregs(self.m_pid).unwrap());
-					self.trace_file.syscalls_append(regs);
-
-					let siginfo_a = ptrace::getsiginfo(self.m_pid).unwrap();
-					//println!("{:?}", siginfo_a);
-				}
-				//it wasnt a syscall signal that caused the pause - probably a breakpoint
-				else {
-					break
-				}
+	//stores each threads registers in the thread table
+	fn refresh_thread_regs(&mut self) {
+		for thread in self.threads.threads.values_mut() {
+			if thread.state != ThreadState::Stopped {
+				continue;
 			}
+			thread.regs = self.target.get_regs(thread.tid).ok();
 		}
-		else if self.trace_state == TraceState::Restoring {
-			ptrace::sysemu(self.m_pid, None);
-			wait::waitpid(self.m_pid, None);
-		}
-		else {
-			ptrace::cont(self.m_pid, None);
-			wait::waitpid(self.m_pid, None);
+	}
+
+	//thread list | l
+	//thread <num>
+	fn handle_thread(&mut self, args: Vec<&str>) {
+		if args.len() < 2 {
+			println!("Current thread is {} (tid {})", self.threads.selected_num(), self.cur_tid());
+			return;
 		}
-		
-		
-		
+		match args[1] {
+			"list" | "l" => {
+				self.threads.list();
+			},
+			_ => {
+				let num = match str_to_int(args[1]) {
+					Some(num) => num,
+					None => {
+						self.fail("Invalid thread number");
+						return;
+					},
+				};
+				match self.threads.select(num) {
+					Ok(tid) => {println!("Switched to thread {} (tid {})", num, tid);},
+					Err(_) => {self.fail(&format!("No thread numbered {}", num));},
+				};
+			},
+		};
 	}
 
-	fn handle_breakpoints(&mut self, args: Vec<&str>) {
+	fn handle_breakpoints(&mut self, args: Vec<&str>, prog: &ProgInfo) {
 	//b list | l
-	//b disable | d <idx>
-	//b enabled | e <idx>
-	//b delete | de <idx>
-	//b <idx>
+	//b disable | d <id>
+	//b enabled | e <id>
+	//b delete | de <id>
+	//b enable once <id>
+	//b cond <id> [<condition>]
+	//b ignore <id> <count>
+	//b <loc> [if <condition>]
+	//b once <loc> [if <condition>]
 		if args.len() < 2 {
-			println!("Breakpoint command needs second argument");
+			self.fail("Breakpoint command needs second argument");
 			return;
 		}
+		//enable once is enable, but the breakpoint disables itself again after its next hit
+		let mut args = args;
+		let once = (args[1] == "enable" || args[1] == "e") && args.get(2) == Some(&"once");
+		if once {
+			args.remove(2);
+		}
 
 		let mut idx = 0;
 		//the commands which need an index specified
 		//need to check its a valid index (or theres on at all)
 		match args[1] {
-			"disable" | "d" | "enable" | "e" | "delete" | "de" => {
+			"disable" | "d" | "enable" | "e" | "delete" | "de" | "cond" | "ignore" => {
 				if args.len() < 3 {
-					println!("Need address to be specified");
+					self.fail("Need id to be specified");
+					return;
 				}
 				match str_to_int(args[2]) {
 					Some(num) => {
 						idx = num;
 					},
 					None => {
-						println!("Invalid index specified");
+						self.fail("Invalid id specified");
 						return;
 					},
 				};
@@ -295,95 +2804,362 @@ impl Debugger<'_> {
 		
 		match args[1] {
 			"list" | "l" => {
-				self.list_breakpoints();
+				self.list_breakpoints(prog);
 			},
 			"disable" | "d" => {
 				if args.len() < 3 {
-					println!("Need index specified to disable breakpoint");
+					self.fail("Need index specified to disable breakpoint");
 				}
 				match self.bp_table.disable(idx) {
 					Ok(_) => {},
+					Err(_) if self.is_watch(idx) => {
+						self.fail(&format!("{} is a watchpoint. They can only be deleted", idx));
+					},
 					Err(_) => {
-						println!("Error disabling breakpoint. Invalid index.");
+						self.fail("Error disabling breakpoint. Invalid id.");
 					},
 				};
 			},
 			"enable" | "e" => {
 				if args.len() < 3 {
-					println!("Need index specified to enable breakpoint");
+					self.fail("Need index specified to enable breakpoint");
 				}
 				match self.bp_table.enable(idx) {
-					Ok(_) => {},
+					Ok(_) => {
+						for id in self.bp_table.ids_in(idx) {
+							let bp = self.bp_table.get_mut(id).unwrap();
+							bp.disposition = if once {Disposition::Disable} else {Disposition::Keep};
+						}
+					},
+					Err(_) if self.is_watch(idx) => {
+						self.fail(&format!("{} is a watchpoint. They can only be deleted", idx));
+					},
 					Err(_) => {
-						println!("Error enabling breakpoint. Invalid index.");
+						self.fail("Error enabling breakpoint. Invalid id, or the address cant be written to.");
 					},
 				};
 			},
 			"delete" | "de" => {
 				if args.len() < 3 {
-					println!("Need index specified to delete breakpoint");
+					self.fail("Need index specified to delete breakpoint");
 				}
-				match self.bp_table.delete(idx) {
+				//watchpoints share ids with breakpoints, so can be deleted from here too
+				match self.bp_table.delete(idx).or_else(|_| self.delete_watch(idx)) {
 					Ok(_) => {},
 					Err(_) => {
-						println!("Error deleting breakpoint. Invalid index.");
+						self.fail("Error deleting breakpoint. Invalid id.");
+					},
+				};
+			},
+			//no condition given removes it
+			"cond" => {
+				let condition = match parse_condition(&args[3..]) {
+					Ok(condition) => condition,
+					Err(err) => {
+						self.fail(&err);
+						return;
+					},
+				};
+				if self.bp_table.set_condition(idx, condition).is_err() {
+					self.fail("Error setting condition. Invalid id.");
+				}
+			},
+			"ignore" => {
+				let count = match args.get(3).and_then(|count| str_to_int(count)) {
+					Some(count) => count as u64,
+					None => {
+						self.fail("Need a number of hits to ignore");
+						return;
+					},
+				};
+				match self.bp_table.get_mut(idx) {
+					Some(bp) => {
+						bp.ignore_count = count;
+						println!("Will ignore next {} crossings of breakpoint {}.", count, idx);
+					},
+					None => {
+						self.fail("Error setting ignore count. Invalid id.");
 					},
 				};
 			},
+			"once" => {
+				if args.len() < 3 {
+					self.fail("Need location specified for breakpoint");
+					return;
+				}
+				self.create_breakpoint(args[1..].to_vec(), prog, Disposition::Disable);
+			},
 			//Default behaviour is to create a breakpoint (if no other command is given)
 			_  => {
-				self.create_breakpoint(args);
+				self.create_breakpoint(args, prog, Disposition::Keep);
 			}
 		}
 	}
 
 
-	fn create_breakpoint(&mut self, args: Vec<&str>) {
+	//b <loc> [if <condition>], or tbreak with the same arguments
+	fn create_breakpoint(&mut self, args: Vec<&str>, prog: &ProgInfo, disposition: Disposition) {
+		let condition = match parse_condition(&args[2..]) {
+			Ok(condition) => condition,
+			Err(err) => {
+				self.fail(&err);
+				return;
+			},
+		};
 		let mut addr = 0;
-		match str_to_int(args[1]) { 
-			Some(num) => {
+		match self.resolve_location(args[1], prog) { 
+			Ok(num) => {
 				addr = num;
 			}
-			None => {
-				println!("Invalid breakpoint address!");
+			Err(err) => {
+				self.fail(&err);
 				return;
 			}
 		};
-		
-		let mut bp = breakpoint::New(self.m_pid, addr);
-		match bp.enable() {
-			Ok(_) => {},
+		//more than one at an address is fine. They share the int3
+		let others : Vec<String> = self.bp_table.ids_at(addr).iter().map(|id| id.to_string()).collect();
+		let id = match self.bp_table.add(addr, disposition) {
+			Ok(id) => id,
 			Err(err_num) => {
-				println!("Failed to enable breakpoint.\n Ptrace read request failed with err {}", err_num);
+				self.fail(&format!("Failed to enable breakpoint.\n Ptrace read request failed with err {}", err_num));
 				return;
 			},
+		};
+		if !others.is_empty() {
+			println!("Note: breakpoint {} also set at pc {:#x}.", others.join(", "), addr);
 		}
-		match self.bp_table.insert(bp) {
-			Ok(_) => {},
-			Err(_) => {
-				println!("Error creating breakpoint. There is already a breakpoint at that address");
+		let kind = if disposition == Disposition::Delete {"Temporary breakpoint"} else {"Breakpoint"};
+		println!("{} {} at {:#x} ({})", kind, id, addr, prog.describe_addr(self.to_file(addr) as u64));
+		self.bp_table.set_condition(id, condition).ok();
+	}
+
+	//dprintf <loc>[,] "format", args...
+	//a breakpoint that prints the format (like printf, args are expressions) and doesnt stop
+	fn create_dprintf(&mut self, line: &str, prog: &ProgInfo) {
+		let rest = line.trim_start().trim_start_matches("dprintf").trim_start();
+		let loc_end = rest.find(|c: char| c == ',' || c.is_whitespace()).unwrap_or(rest.len());
+		let (loc, rest) = rest.split_at(loc_end);
+		if loc.is_empty() {
+			self.fail("Dprintf command needs to be formatted: dprintf <loc> \"format\", args...");
+			return;
+		}
+		let rest = rest.trim_start();
+		let fmt = match dprintf_fmt::parse(rest.strip_prefix(',').unwrap_or(rest)) {
+			Ok(fmt) => fmt,
+			Err(err) => {
+				self.fail(&err);
+				return;
+			},
+		};
+		let addr = match self.resolve_location(loc, prog) {
+			Ok(addr) => addr,
+			Err(err) => {
+				self.fail(&err);
+				return;
+			},
+		};
+		let id = match self.bp_table.add(addr, Disposition::Keep) {
+			Ok(id) => id,
+			Err(err_num) => {
+				self.fail(&format!("Failed to enable dprintf.\n Ptrace read request failed with err {}", err_num));
+				return;
+			},
+		};
+		self.bp_table.get_mut(id).unwrap().dprintf = Some(fmt);
+		println!("Dprintf {} at {:#x} ({})", id, addr, prog.describe_addr(self.to_file(addr) as u64));
+	}
+
+	//where a breakpoint (or anything else that takes a code location) should go. Accepts:
+	//	*0x401000, 0x401000	an address. Below the load address of a PIE program its a file address
+	//	main, mymod::func	a function, just past its prologue
+	//	func+0x10			an offset into a function
+	//	file.c:42			the first code for a source line
+	pub fn resolve_location(&self, loc: &str, prog: &ProgInfo) -> Result<usize, String> {
+		if let Some(addr) = loc.strip_prefix('*') {
+			return self.parse_code_addr(addr).ok_or(format!("Invalid address {}", addr));
+		}
+		if let Some(addr) = self.parse_code_addr(loc) {
+			return Ok(addr);
+		}
+
+		//file:line. Has to be told apart from mod::func
+		if let Some((file, line)) = loc.rsplit_once(':') {
+			if !file.ends_with(':') && !file.is_empty() {
+				let line = match str_to_int(line) {
+					Some(line) => line as u64,
+					None => {return Err(format!("Invalid line number {}", line));},
+				};
+				return match prog.addr_for_line(file, line) {
+					Some(addr) => Ok(self.to_runtime(addr as usize)),
+					None => Err(format!("No code found for line {} of {}", line, file)),
+				};
+			}
+		}
+
+		let (name, offset) = match loc.rsplit_once('+') {
+			Some((name, offset)) => {
+				match str_to_int(offset) {
+					Some(offset) => (name, Some(offset)),
+					None => {return Err(format!("Invalid offset {}", offset));},
+				}
 			},
+			None => (loc, None),
 		};
+		let addr = match (prog.lookup_symbol(name), offset) {
+			(Some(sym), Some(offset)) => sym.addr + offset as u64,
+			(Some(sym), None) => prog.after_prologue(sym),
+			//stripped. DWARF might still know it
+			(None, _) => {
+				match prog.lookup_function(name) {
+					Some(addr) => addr + offset.unwrap_or(0) as u64,
+					None => {return Err(format!("No function named {}", name));},
+				}
+			},
+		};
+		Ok(self.to_runtime(addr as usize))
 	}
 
 	//list of breakpoints (their addresses and if they're enbaled
-	fn list_breakpoints(&self) {
-		println!("<idx>: <set?>: <addr>");
-		for (idx, bp_maybe) in self.bp_table.bp_list.iter().enumerate() {
-			match bp_maybe {
-				Some(bp) => {
-					println!("<{}>: <{}>: <{:#x}>", idx, bp.enabled, bp.addr); 
+	//rbreak groups are listed where their id comes, with their breakpoints indented under them
+	fn list_breakpoints(&self, prog: &ProgInfo) {
+		println!("<id>: <set?>: <disp>: <addr>: <location>");
+		let mut ids : Vec<usize> = self.bp_table.bps.keys().filter(|id| self.bp_table.group_of(**id).is_none()).cloned().collect();
+		ids.extend(self.bp_table.groups.keys());
+		ids.sort();
+		for id in ids {
+			match self.bp_table.groups.get(&id) {
+				Some(group) => {
+					let enabled = group.members.keys().filter(|member| self.bp_table.get(**member).is_some_and(|bp| bp.enabled)).count();
+					println!("<{}>: <{}/{}>: <group>: <{} breakpoints>: <{}>", id, enabled, group.members.len(), group.members.len(), group.desc);
+					for member in group.members.keys() {
+						self.list_breakpoint(self.bp_table.get(*member).unwrap(), "\t", prog);
+					}
 				},
-				None => {},
+				None => {self.list_breakpoint(self.bp_table.get(id).unwrap(), "", prog);},
+			};
+		}
+	}
+
+	fn list_breakpoint(&self, bp: &bp_entry, indent: &str, prog: &ProgInfo) {
+		let disposition = match bp.disposition {
+			Disposition::Keep => "keep",
+			Disposition::Delete => "del",
+			Disposition::Disable => "dis",
+		};
+		println!("{}<{}>: <{}>: <{}>: <{:#x}>: <{}>", indent, bp.id, bp.enabled, disposition, bp.addr, self.describe_bp(bp.id, bp.addr, prog));
+		if let Some(condition) = &bp.condition {
+			println!("{}\tstop only if {}", indent, condition);
+		}
+		if bp.hits > 0 {
+			println!("{}\tbreakpoint already hit {} time{}", indent, bp.hits, if bp.hits == 1 {""} else {"s"});
+		}
+		if bp.ignore_count > 0 {
+			println!("{}\tWill ignore next {} crossings of breakpoint.", indent, bp.ignore_count);
+		}
+		if let Some(fmt) = &bp.dprintf {
+			println!("{}\tprintf {}", indent, fmt.describe());
+		}
+		for command in &bp.commands {
+			println!("{}\t\t{}", indent, command);
+		}
+	}
+
+	//where a breakpoint is. Ones rbreak put in a library arent in the programs symbols, so the function name rbreak found is used
+	fn describe_bp(&self, id: usize, addr: usize, prog: &ProgInfo) -> String {
+		let desc = prog.describe_addr(self.to_file(addr) as u64);
+		if !desc.starts_with("??") {
+			return desc;
+		}
+		match self.bp_table.group_of(id).and_then(|group| group.members.get(&id)) {
+			Some(func) => func.clone(),
+			None => desc,
+		}
+	}
+
+	//rbreak <regex> [module]
+	//a breakpoint on every function whose name matches, grouped under one id
+	//module is the program, or a shared library it has loaded (libc, libfoo.so.1, or its full path). Default is the program
+	fn handle_rbreak(&mut self, args: Vec<&str>, prog: &ProgInfo) {
+		if args.len() < 2 {
+			self.fail("Rbreak command needs to be formatted: rbreak <regex> [module]");
+			return;
+		}
+		let re = match Regex::new(args[1]) {
+			Ok(re) => re,
+			Err(err) => {
+				self.fail(&format!("Invalid regex {}: {}", args[1], err));
+				return;
+			},
+		};
+		let prog_name = prog.path.rsplit('/').next().unwrap_or("");
+		//(runtime address, function name) of each match
+		let funcs : Vec<(usize, String)> = match args.get(2) {
+			Some(module) if *module != prog_name && *module != prog.path => {
+				let path = match self.find_module(module) {
+					Ok(path) => path,
+					Err(err) => {
+						self.fail(&err);
+						return;
+					},
+				};
+				let lib = ProgInfo::New(&path);
+				let bias = match parse_elf_layout(&path).and_then(|layout| find_load_bias(&*self.target, self.m_pid, &path, &layout)) {
+					Some(bias) => bias,
+					None => {
+						self.fail(&format!("Could not work out where {} is loaded", path));
+						return;
+					},
+				};
+				lib.functions_matching(&re).iter().map(|sym| ((lib.after_prologue(sym) + bias) as usize, sym.name.clone())).collect()
+			},
+			_ => prog.functions_matching(&re).iter().map(|sym| (self.to_runtime(prog.after_prologue(sym) as usize), sym.name.clone())).collect(),
+		};
+		if funcs.is_empty() {
+			self.fail(&format!("No functions match {}", args[1]));
+			return;
+		}
+
+		let group = self.bp_table.new_group(&args[..args.len().min(3)].join(" "));
+		let mut failed = Vec::new();
+		for (addr, name) in &funcs {
+			match self.bp_table.add(*addr, Disposition::Keep) {
+				Ok(id) => {self.bp_table.join(group, id, name);},
+				Err(_) => {failed.push(name.as_str());},
 			};
 		}
+		if !failed.is_empty() {
+			println!("Could not set breakpoints on: {}", failed.join(", "));
+		}
+		if failed.len() == funcs.len() {
+			self.bp_table.groups.remove(&group);
+			self.fail("No breakpoints could be set");
+			return;
+		}
+		println!("Breakpoint group {}: {} breakpoints on functions matching {}", group, funcs.len() - failed.len(), args[1]);
+	}
+
+	//path of a loaded shared library, from a name like libc or libc.so.6
+	fn find_module(&self, module: &str) -> Result<String, String> {
+		let mut paths : Vec<String> = Vec::new();
+		for (_, offset, path) in self.target.file_mappings(self.m_pid) {
+			let file = path.rsplit('/').next().unwrap_or("");
+			let matches = path == module || file == module || file.starts_with(&format!("{}.", module)) || file.starts_with(&format!("{}-", module));
+			if offset == 0 && matches && !paths.contains(&path) {
+				paths.push(path);
+			}
+		}
+		match paths.len() {
+			0 => Err(format!("No loaded module matches {}", module)),
+			1 => Ok(paths.remove(0)),
+			_ => Err(format!("{} matches more than one module: {}", module, paths.join(", "))),
+		}
 	}
 
-	fn handle_regs(&self, args: Vec<&str>) {
-		let regs = match  ptrace::getregs(self.m_pid) {
+	fn handle_regs(&mut self, args: Vec<&str>) {
+		let regs = match self.target.get_regs(self.cur_tid()) {
 			Ok(regs_val) => regs_val,
 			Err(err_num) => {
-				println!("Failed to retrieve registers with ptrace.\n Error code was {}", err_num);
+				self.fail(&format!("Failed to retrieve registers with ptrace.\n Error code was {}", err_num));
 				return;
 			},
 		};
@@ -403,6 +3179,7 @@ impl Debugger<'_> {
 				fmt = f;
 			},
 			None => {
+				self.failed.set(true);
 				return
 			},
 		}
@@ -445,7 +3222,7 @@ impl Debugger<'_> {
 					user_num = num as u64;
 				} 
 				None => {
-					println!("Invalid value to set register to!");
+					self.fail("Invalid value to set register to!");
 					return;
 				}
 			};
@@ -457,7 +3234,7 @@ impl Debugger<'_> {
 					orig_reg_val = *num;
 				},
 				None => {
-					println!("Invalid register name");
+					self.fail("Invalid register name");
 					return;
 				}
 			}
@@ -473,10 +3250,12 @@ impl Debugger<'_> {
 			//and that scenario occurs when the user specifies 8 bytes (e.g no bitmask)
 			println!("{:?}", fmt);
 			let modified_val = fmt.trim_val(user_num) | (orig_reg_val & (u64::MAX -  ( (2u128.pow((fmt.n_bytes*8) as u32) -1) as u64) ) );
-			match Debugger::set_reg(self.m_pid, args[2], modified_val) {
-				Ok(_) => {},
+			match Debugger::set_reg(self.cur_tid(), args[2], modified_val) {
+				Ok(_) => {
+					self.refresh_thread_regs();
+				},
 				Err(err_num) => {
-					println!("Failed to write to register.\n Error in ptrace request. Error code was {}", err_num);
+					self.fail(&format!("Failed to write to register.\n Error in ptrace request. Error code was {}", err_num));
 				}
 			}
 		}
@@ -485,7 +3264,7 @@ impl Debugger<'_> {
 
 	fn handle_mem(&self, args: Vec<&str>) {
 		if args.len() < 3 {
-			println!("Memory command needs to be formatted: mem r/w addr");
+			self.fail("Memory command needs to be formatted: mem r/w addr");
 			return;
 		}
 
@@ -495,6 +3274,7 @@ impl Debugger<'_> {
 				fmt = f;
 			},
 			None => {
+				self.failed.set(true);
 				return
 			},
 		}
@@ -506,7 +3286,7 @@ impl Debugger<'_> {
 					addr = num;
 				},
 				None => {
-					println!("No address specified");
+					self.fail("No address specified");
 					return;
 				},
 			};
@@ -514,7 +3294,7 @@ impl Debugger<'_> {
 			let mut mem_val = match self.read_mem(addr) {
 				Ok(mem) => mem,
 				Err(err_num) => {
-					println!("Failed to read memory value from address.\n Error in ptrace request. Error code was {}", err_num);
+					self.fail(&format!("Failed to read memory value from address.\n Error in ptrace request. Error code was {}", err_num));
 					return;
 				},
 			};
@@ -536,7 +3316,7 @@ impl Debugger<'_> {
 					addr = num as usize;
 				} 
 				None => {
-					println!("No address specified");
+					self.fail("No address specified");
 					return;
 				}
 			};
@@ -547,7 +3327,7 @@ impl Debugger<'_> {
 					user_num = num as u64;
 				} 
 				None => {
-					println!("Invalid value to set memory to!!");
+					self.fail("Invalid value to set memory to!!");
 					return;
 				}
 			};
@@ -555,7 +3335,7 @@ impl Debugger<'_> {
 			let orig_mem_val = match self.read_mem(addr) {
 				Ok(mem) => mem,
 				Err(err_num) => {
-					println!("Failed to read memory value from address, as to prepare for write.\n Error in ptrace request. Error code was {}", err_num);
+					self.fail(&format!("Failed to read memory value from address, as to prepare for write.\n Error in ptrace request. Error code was {}", err_num));
 					return;
 				},
 			};
@@ -572,3 +3352,109 @@ impl Debugger<'_> {
 	}
 }
 
+
+//what expressions (breakpoint conditions) see. The state of one stopped thread
+struct stop_env<'a, 'b> {
+	dbg: &'a Debugger<'b>,
+	prog: &'a ProgInfo,
+	tid: Pid,
+	regs: HashMap<&'static str, u64>,
+}
+
+impl ExprEnv for stop_env<'_, '_> {
+	fn reg(&self, name: &str) -> Option<u64> {
+		self.regs.get(name).cloned()
+	}
+
+	fn read(&self, addr: u64) -> Result<u64, String> {
+		self.dbg.target.read_mem(self.tid, addr as usize).map_err(|_| format!("Cannot access memory at {:#x}", addr))
+	}
+
+	fn var(&self, name: &str) -> Result<i64, String> {
+		let pc = self.dbg.to_file(self.regs["rip"] as usize) as u64;
+		let var = match self.prog.find_variable(pc, name) {
+			Some(var) => var,
+			None => {return Err(format!("No variable or register called {}", name));},
+		};
+		if var.size > 8 {
+			return Err(format!("{} is not an integer or pointer", name));
+		}
+		let cfa = self.prog.frames.as_ref().and_then(|frames| frames.cfa(pc, &self.regs));
+		let read = |addr| self.dbg.target.read_mem(self.tid, addr as usize).ok();
+		let bias = self.dbg.load_bias as u64;
+		let frame_base = match &var.frame_base {
+			Some(expr) => match eval_location(expr, var.encoding, None, cfa, bias, &self.regs, &read)? {
+				gimli::Location::Address { address } => Some(address),
+				_ => None,
+			},
+			None => None,
+		};
+		let raw = match eval_location(&var.location, var.encoding, frame_base, cfa, bias, &self.regs, &read)? {
+			gimli::Location::Address { address } => self.read(address)?,
+			gimli::Location::Register { register } => {
+				*dwarf_reg_name(register.0).and_then(|reg| self.regs.get(reg)).ok_or(format!("{} is in an unsupported register", name))?
+			},
+			gimli::Location::Value { value } => value.to_u64(!0).map_err(|_| format!("{} has an unsupported value", name))?,
+			_ => {return Err(format!("{} has been optimised out", name));},
+		};
+		Ok(truncate(raw, int_type { size: var.size as u32, signed: var.signed }))
+	}
+}
+
+
+//low 8 bytes of xmm0. Where a float or double is returned
+fn get_xmm0(tid: Pid) -> Option<u64> {
+	let mut fpregs : libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+	let res = unsafe { libc::ptrace(libc::PTRACE_GETFPREGS, tid.as_raw(), 0, &mut fpregs as *mut libc::user_fpregs_struct) };
+	if res < 0 {
+		return None;
+	}
+	Some(fpregs.xmm_space[0] as u64 | (fpregs.xmm_space[1] as u64) << 32)
+}
+
+//PTRACE_SYSCALL instead of PTRACE_CONT when syscalls are being caught, so the thread stops at each one
+fn resume(tid: Pid, sig: Option<Signal>, syscalls: bool) {
+	if syscalls {
+		ptrace::syscall(tid, sig).ok();
+	}
+	else {
+		ptrace::cont(tid, sig).ok();
+	}
+}
+
+//the condition at the end of a breakpoint command ("if" has already been taken off for b cond)
+//checked for mistakes now, rather than on the first hit
+fn parse_condition(args: &[&str]) -> Result<Option<String>, String> {
+	let args = match args.first() {
+		Some(&"if") => &args[1..],
+		_ => args,
+	};
+	if args.is_empty() {
+		return Ok(None);
+	}
+	let condition = args.join(" ");
+	parse_expr(&condition)?;
+	Ok(Some(condition))
+}
+
+//commands that only look at the process, so also work on a core file
+fn reads_state(args: &[&str]) -> bool {
+	match args[0] {
+		"registers" | "regs" | "r" | "memory" | "mem" | "m" => args.len() < 2 || !args[1].contains('w'),
+		"thread" | "t" => true,
+		"break" | "breakpoint" | "b" => args.len() > 1 && (args[1] == "list" || args[1] == "l"),
+		_ => false,
+	}
+}
+
+//whether a command needs a live, traced process to do anything
+fn needs_process(args: &[&str]) -> bool {
+	match args[0] {
+		"continue" | "cont" | "c" | "registers" | "regs" | "r" | "memory" | "mem" | "m" |
+		"s" | "si" | "ni" | "step" | "next" | "n" | "finish" | "fin" | "snapshot" | "restore" | "detach" | "thread" | "t" | "signal" | "kill" | "gcore" | "tbreak" |
+		"hbreak" | "watch" | "rwatch" | "awatch" | "dprintf" | "rbreak" => true,
+		//listing breakpoints is fine, creating/changing them isnt
+		"break" | "breakpoint" | "b" | "catch" => args.len() < 2 || !(args[1] == "list" || args[1] == "l"),
+		_ => false,
+	}
+}
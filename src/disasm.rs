@@ -0,0 +1,71 @@
+use iced_x86::{Decoder, DecoderOptions, Formatter, IntelFormatter, FlowControl};
+
+
+//longest an x86 instruction can be
+pub const MAX_INSN_LEN: usize = 15;
+
+//one instruction, as shown after si/ni
+pub struct insn {
+	//intel syntax, e.g "mov rbp,rsp"
+	pub text: String,
+	pub len: usize,
+	//ni runs over these rather than stepping into them
+	pub is_call: bool,
+}
+
+//first instruction in bytes, which were read from ip. None if its not a valid one (or got cut off)
+pub fn decode(bytes: &[u8], ip: u64) -> Option<insn> {
+	let mut decoder = Decoder::with_ip(64, bytes, ip, DecoderOptions::NONE);
+	let instr = decoder.decode();
+	if instr.is_invalid() {
+		return None;
+	}
+	let mut formatter = IntelFormatter::new();
+	formatter.options_mut().set_hex_prefix("0x");
+	formatter.options_mut().set_hex_suffix("");
+	formatter.options_mut().set_uppercase_hex(false);
+	formatter.options_mut().set_branch_leading_zeros(false);
+	formatter.options_mut().set_first_operand_char_index(8);
+	let mut text = String::new();
+	formatter.format(&instr, &mut text);
+	Some(insn {
+		text,
+		len: instr.len(),
+		is_call: instr.flow_control() == FlowControl::Call || instr.flow_control() == FlowControl::IndirectCall,
+	})
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decodes_one() {
+		//push rbp, then mov rbp,rsp. Only the first is decoded
+		let insn = decode(&[0x55, 0x48, 0x89, 0xe5], 0x1000).unwrap();
+		assert_eq!(insn.text, "push    rbp");
+		assert_eq!(insn.len, 1);
+		assert!(!insn.is_call);
+	}
+
+	#[test]
+	fn calls() {
+		//call rel32 to 0x1005 + 0x10, and call rax
+		let insn = decode(&[0xe8, 0x10, 0, 0, 0], 0x1000).unwrap();
+		assert_eq!(insn.text, "call    0x1015");
+		assert_eq!(insn.len, 5);
+		assert!(insn.is_call);
+		assert!(decode(&[0xff, 0xd0], 0x1000).unwrap().is_call);
+		//jmp isnt a call
+		assert!(!decode(&[0xeb, 0x00], 0x1000).unwrap().is_call);
+	}
+
+	#[test]
+	fn invalid() {
+		assert!(decode(&[], 0x1000).is_none());
+		//cut off in the middle
+		assert!(decode(&[0xe8, 0x10], 0x1000).is_none());
+		assert!(decode(&[0x06], 0x1000).is_none());
+	}
+}
@@ -0,0 +1,325 @@
+use crate::expr::{ExprEnv, parse_expr, eval, truncate, int_type};
+
+
+//longest string %s will read out of the debugee
+const MAX_STRING: usize = 256;
+
+//what a dprintf prints each time its hit. Checked when its made, worked out again on every hit
+#[derive(Clone, Debug)]
+pub struct dprintf_fmt {
+	pub format: String,
+	//one expression (see expr.rs) per % in the format
+	pub args: Vec<String>,
+}
+
+//one piece of the format string
+enum piece {
+	Text(String),
+	Conv(conversion),
+}
+
+//%[flags][width][.precision][length]<kind>
+struct conversion {
+	left: bool,
+	zero: bool,
+	plus: bool,
+	space: bool,
+	alt: bool,
+	width: usize,
+	precision: Option<usize>,
+	//bytes the value is cut down to (hh, h, none, l)
+	size: u32,
+	kind: char,
+}
+
+impl dprintf_fmt {
+	//"format", arg, arg... (what comes after the location of dprintf)
+	pub fn parse(text: &str) -> Result<dprintf_fmt, String> {
+		let text = text.trim();
+		if !text.starts_with('"') {
+			return Err(String::from("Dprintf needs a format string in double quotes"));
+		}
+		let (format, rest) = parse_string(&text[1..])?;
+		let rest = rest.trim();
+		let mut args = Vec::new();
+		if !rest.is_empty() {
+			let rest = match rest.strip_prefix(',') {
+				Some(rest) => rest,
+				None => {return Err(String::from("Arguments after the format string need to be separated by commas"));},
+			};
+			//expressions dont have commas in them, so splitting on them is safe
+			for arg in rest.split(',') {
+				let arg = arg.trim();
+				parse_expr(arg)?;
+				args.push(arg.to_string());
+			}
+		}
+		let wanted = parse_format(&format)?.iter().filter(|piece| match piece {
+			piece::Conv(conv) => conv.kind != '%',
+			_ => false,
+		}).count();
+		if wanted != args.len() {
+			return Err(format!("Format string wants {} argument{}, but {} {} given", wanted, if wanted == 1 {""} else {"s"}, args.len(), if args.len() == 1 {"was"} else {"were"}));
+		}
+		Ok(dprintf_fmt { format, args })
+	}
+
+	//the format and args as they would be typed in
+	pub fn describe(&self) -> String {
+		let mut shown = format!("{:?}", self.format);
+		for arg in &self.args {
+			shown.push_str(", ");
+			shown.push_str(arg);
+		}
+		shown
+	}
+
+	//the text printed for a hit. Follows printf, so theres no newline unless the format has one
+	pub fn render(&self, env: &dyn ExprEnv) -> Result<String, String> {
+		let mut out = String::new();
+		let mut args = self.args.iter();
+		for piece in parse_format(&self.format)? {
+			let conv = match piece {
+				piece::Text(text) => {
+					out.push_str(&text);
+					continue;
+				},
+				piece::Conv(conv) => conv,
+			};
+			if conv.kind == '%' {
+				out.push('%');
+				continue;
+			}
+			//parse checked there are enough
+			let arg = args.next().unwrap();
+			let val = eval(&parse_expr(arg)?, env)?;
+			out.push_str(&conv.apply(val, env)?);
+		}
+		Ok(out)
+	}
+}
+
+impl conversion {
+	fn apply(&self, val: i64, env: &dyn ExprEnv) -> Result<String, String> {
+		let signed = int_type { size: self.size, signed: true };
+		let unsigned = int_type { size: self.size, signed: false };
+		let (sign, mut digits, prefix) = match self.kind {
+			'd' | 'i' => {
+				let val = truncate(val as u64, signed);
+				let sign = if val < 0 {"-"} else if self.plus {"+"} else if self.space {" "} else {""};
+				(sign, val.unsigned_abs().to_string(), "")
+			},
+			'u' => ("", (truncate(val as u64, unsigned) as u64).to_string(), ""),
+			'x' => ("", format!("{:x}", truncate(val as u64, unsigned)), if self.alt && val != 0 {"0x"} else {""}),
+			'X' => ("", format!("{:X}", truncate(val as u64, unsigned)), if self.alt && val != 0 {"0X"} else {""}),
+			'o' => ("", format!("{:o}", truncate(val as u64, unsigned)), if self.alt {"0"} else {""}),
+			'p' => ("", format!("{:x}", val as u64), "0x"),
+			'c' => {
+				return Ok(self.pad(String::from((val as u8) as char)));
+			},
+			_ => {
+				let mut text = read_string(val as u64, env)?;
+				if let Some(precision) = self.precision {
+					text = text.chars().take(precision).collect();
+				}
+				return Ok(self.pad(text));
+			},
+		};
+		//precision on an integer is the least number of digits
+		if let Some(precision) = self.precision {
+			while digits.len() < precision {
+				digits.insert(0, '0');
+			}
+		}
+		let mut text = format!("{}{}", prefix, digits);
+		//zeros go between the sign/0x and the digits, not in front of them
+		if self.zero && !self.left && self.precision.is_none() {
+			while sign.len() + text.len() < self.width {
+				text.insert(prefix.len(), '0');
+			}
+		}
+		Ok(self.pad(format!("{}{}", sign, text)))
+	}
+
+	fn pad(&self, text: String) -> String {
+		let len = text.chars().count();
+		if len >= self.width {
+			return text;
+		}
+		let spaces = " ".repeat(self.width - len);
+		if self.left {text + &spaces} else {spaces + &text}
+	}
+}
+
+//the rest of a "..." string, after the opening quote. Returns it with escapes worked out, and whatever came after it
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+	let mut out = String::new();
+	let mut chars = text.char_indices();
+	while let Some((idx, c)) = chars.next() {
+		match c {
+			'"' => {return Ok((out, &text[idx+1..]));},
+			'\\' => {
+				let escaped = match chars.next() {
+					Some((_, escaped)) => escaped,
+					None => {break;},
+				};
+				out.push(match escaped {
+					'n' => '\n',
+					't' => '\t',
+					'r' => '\r',
+					'0' => '\0',
+					'e' => '\x1b',
+					other => other,
+				});
+			},
+			_ => {out.push(c);},
+		};
+	}
+	Err(String::from("Format string is missing its closing quote"))
+}
+
+fn parse_format(format: &str) -> Result<Vec<piece>, String> {
+	let mut pieces = Vec::new();
+	let mut text = String::new();
+	let mut chars = format.chars().peekable();
+	while let Some(c) = chars.next() {
+		if c != '%' {
+			text.push(c);
+			continue;
+		}
+		if !text.is_empty() {
+			pieces.push(piece::Text(text.clone()));
+			text.clear();
+		}
+		let mut conv = conversion { left: false, zero: false, plus: false, space: false, alt: false, width: 0, precision: None, size: 4, kind: '%' };
+		while let Some(flag) = chars.peek() {
+			match flag {
+				'-' => {conv.left = true;},
+				'0' => {conv.zero = true;},
+				'+' => {conv.plus = true;},
+				' ' => {conv.space = true;},
+				'#' => {conv.alt = true;},
+				_ => {break;},
+			};
+			chars.next();
+		}
+		while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+			conv.width = conv.width*10 + digit as usize;
+			chars.next();
+		}
+		if chars.peek() == Some(&'.') {
+			chars.next();
+			let mut precision = 0;
+			while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+				precision = precision*10 + digit as usize;
+				chars.next();
+			}
+			conv.precision = Some(precision);
+		}
+		//length modifiers. Everything is 64 bits here, so these only decide what its cut down to
+		while let Some(len) = chars.peek() {
+			match len {
+				'h' => {conv.size = if conv.size == 2 {1} else {2};},
+				'l' | 'z' | 'j' | 't' | 'q' => {conv.size = 8;},
+				_ => {break;},
+			};
+			chars.next();
+		}
+		conv.kind = match chars.next() {
+			Some(kind) if "diuxXopcs%".contains(kind) => kind,
+			Some(kind) => {return Err(format!("Unsupported conversion %{} in format string", kind));},
+			None => {return Err(String::from("Format string ends in the middle of a conversion"));},
+		};
+		pieces.push(piece::Conv(conv));
+	}
+	if !text.is_empty() {
+		pieces.push(piece::Text(text));
+	}
+	Ok(pieces)
+}
+
+//a NUL terminated string out of the debugee
+fn read_string(addr: u64, env: &dyn ExprEnv) -> Result<String, String> {
+	if addr == 0 {
+		return Ok(String::from("(null)"));
+	}
+	let mut bytes = Vec::new();
+	'outer: while bytes.len() < MAX_STRING {
+		let word = env.read(addr + bytes.len() as u64)?;
+		for byte in word.to_le_bytes().iter() {
+			if *byte == 0 {
+				break 'outer;
+			}
+			bytes.push(*byte);
+		}
+	}
+	bytes.truncate(MAX_STRING);
+	Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	//rax = -5, rdi points at "hi". Theres no DWARF, so no variables
+	struct fake_env;
+
+	impl ExprEnv for fake_env {
+		fn reg(&self, name: &str) -> Option<u64> {
+			match name {
+				"rax" => Some(-5i64 as u64),
+				"rdi" => Some(0x1000),
+				_ => None,
+			}
+		}
+
+		fn read(&self, addr: u64) -> Result<u64, String> {
+			match addr {
+				0x1000 => Ok(u64::from_le_bytes(*b"hi\0xxxxx")),
+				_ => Err(format!("Cannot access memory at {:#x}", addr)),
+			}
+		}
+
+		fn var(&self, name: &str) -> Result<i64, String> {
+			Err(format!("No variable {}", name))
+		}
+	}
+
+	fn render(text: &str) -> Result<String, String> {
+		dprintf_fmt::parse(text)?.render(&fake_env)
+	}
+
+	#[test]
+	fn conversions() {
+		assert_eq!(render(r#""%d %i %u\n", rax, 7, rax"#), Ok(String::from("-5 7 4294967291\n")));
+		assert_eq!(render(r#""%x %X %#x %o", 255, 255, 255, 8"#), Ok(String::from("ff FF 0xff 10")));
+		assert_eq!(render(r#""%p %c %s", 0x10, 65, rdi"#), Ok(String::from("0x10 A hi")));
+		assert_eq!(render(r#""100%%""#), Ok(String::from("100%")));
+		assert_eq!(render(r#""%s", 0"#), Ok(String::from("(null)")));
+	}
+
+	#[test]
+	fn width_and_flags() {
+		assert_eq!(render(r#""[%5d|%-5d|%05d|%+d]", 42, 42, -42, 42"#), Ok(String::from("[   42|42   |-0042|+42]")));
+		assert_eq!(render(r#""[%.3d|%#06x|%.1s]", 7, 10, rdi"#), Ok(String::from("[007|0x000a|h]")));
+	}
+
+	#[test]
+	fn lengths() {
+		assert_eq!(render(r#""%hhd %hd %ld", 0x1ff, 0x18000, rax"#), Ok(String::from("-1 -32768 -5")));
+		assert_eq!(render(r#""%lx %lu", rax, 1"#), Ok(String::from("fffffffffffffffb 1")));
+	}
+
+	#[test]
+	fn bad_formats() {
+		assert!(dprintf_fmt::parse("%d, 1").is_err());
+		assert!(dprintf_fmt::parse(r#""%d""#).is_err());
+		assert!(dprintf_fmt::parse(r#""%d", 1, 2"#).is_err());
+		assert!(dprintf_fmt::parse(r#""%q", 1"#).is_err());
+		assert!(dprintf_fmt::parse(r#""%d" 1"#).is_err());
+		assert!(dprintf_fmt::parse(r#""unterminated"#).is_err());
+		assert!(dprintf_fmt::parse(r#""%d", 1 +"#).is_err());
+		assert!(render(r#""%s", rsp"#).is_err());
+	}
+}
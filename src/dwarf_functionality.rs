@@ -1,7 +1,68 @@
 use std::ops::Range;
+use std::rc::Rc;
+use std::fs;
+use std::collections::HashMap;
 
 use gimli::read::Dwarf;
-use gimli::{DwAt,EndianSlice,RunTimeEndian,AttributeValue,DebuggingInformationEntry};
+use gimli::{DwAt,RunTimeEndian,AttributeValue,DebuggingInformationEntry,EndianRcSlice};
+use object::{Object, ObjectSection};
+use gimli::UnwindSection;
+
+//Owns its section data, so it can be reloaded (e.g when attaching to a different program) without borrowing the file
+pub type DwarfInfo = Dwarf<EndianRcSlice<RunTimeEndian>>;
+//a variable found in a DwarfInfo
+pub type DwarfVar = var_info<EndianRcSlice<RunTimeEndian>>;
+
+//a row of the line program. Addresses are file addresses
+#[derive(Clone, Debug)]
+pub struct line_row {
+	pub addr: u64,
+	pub file: String,
+	pub line: u64,
+	//first address past a sequence of code. Doesnt belong to any line
+	pub end_sequence: bool,
+}
+
+
+pub fn load_dwarf(path: &str) -> Result<DwarfInfo, String> {
+	let bin_data = match fs::read(path) {
+		Ok(data) => data,
+		Err(err) => {return Err(format!("Failed to read {}. Error was {:?}", path, err.kind()));},
+	};
+	let obj_file = match object::read::File::parse(&*bin_data) {
+		Ok(file) => file,
+		Err(err) => {return Err(format!("Failed to parse {}. Error was {}", path, err));},
+	};
+	let endian = if obj_file.is_little_endian() {RunTimeEndian::Little} else {RunTimeEndian::Big};
+
+	let load_section = |id: gimli::SectionId| -> Result<EndianRcSlice<RunTimeEndian>, gimli::Error> {
+		let data : Rc<[u8]> = match obj_file.section_by_name(id.name()) {
+			//section has been found
+			//can potentially fail to decompress. If so, use empty section
+			Some(section) => {
+				match section.uncompressed_data() {
+					Ok(cow) => Rc::from(&*cow),
+					Err(_) => Rc::from(&[][..]),
+				}
+			},
+			None => Rc::from(&[][..]),
+		};
+		Ok(EndianRcSlice::new(data, endian))
+	};
+
+	match Dwarf::load(&load_section, &load_section) {
+		Ok(dwarf) => Ok(dwarf),
+		Err(err) => Err(format!("Failed to load DWARF info from {}. Error was {}", path, err)),
+	}
+}
+
+//used when there is no debug info to be had (program couldnt be read)
+pub fn empty_dwarf() -> DwarfInfo {
+	let load_section = |_: gimli::SectionId| -> Result<EndianRcSlice<RunTimeEndian>, gimli::Error> {
+		Ok(EndianRcSlice::new(Rc::from(&[][..]), RunTimeEndian::Little))
+	};
+	Dwarf::load(&load_section, &load_section).unwrap()
+}
 
 //Entire dwarf object
 pub fn get_func_from_pc<'a, R: gimli::Reader>(dwarf_info: &'a Dwarf<R>,pc:u64) -> Option<gimli::EntriesCursor<R>> {
@@ -58,6 +119,68 @@ pub fn get_func_from_pc<'a, R: gimli::Reader>(dwarf_info: &'a Dwarf<R>,pc:u64) -
 }
 
 
+//low pc of the function with this name. For when the symbol table has been stripped
+pub fn find_function<R: gimli::Reader>(dwarf_info: &Dwarf<R>, name: &str) -> Option<u64> {
+	let mut compilation_units = dwarf_info.units();
+	while let Ok(Some(compilation_unit)) = compilation_units.next() {
+		let unit = match dwarf_info.unit(compilation_unit) {
+			Ok(unit) => unit,
+			Err(_) => {continue;},
+		};
+		let mut DIE_tree = unit.entries();
+		while let Ok(Some((_, cur_DIE))) = DIE_tree.next_dfs() {
+			if cur_DIE.tag() != gimli::DW_TAG_subprogram {
+				continue;
+			}
+			let func_name = match cur_DIE.attr_value(gimli::DW_AT_name) {
+				Ok(Some(attr)) => attr,
+				_ => {continue;},
+			};
+			let matches = match dwarf_info.attr_string(&unit, func_name) {
+				Ok(string) => string.to_string().map(|string| string == name).unwrap_or(false),
+				Err(_) => false,
+			};
+			if matches {
+				if let Some(range) = get_pc_range(cur_DIE) {
+					return Some(range.start);
+				}
+			}
+		}
+	}
+	None
+}
+
+
+//name and address range of every function with code. For when the symbol table has been stripped
+pub fn list_functions<R: gimli::Reader>(dwarf_info: &Dwarf<R>) -> Vec<(String, Range<u64>)> {
+	let mut funcs = Vec::new();
+	let mut compilation_units = dwarf_info.units();
+	while let Ok(Some(compilation_unit)) = compilation_units.next() {
+		let unit = match dwarf_info.unit(compilation_unit) {
+			Ok(unit) => unit,
+			Err(_) => {continue;},
+		};
+		let mut DIE_tree = unit.entries();
+		while let Ok(Some((_, cur_DIE))) = DIE_tree.next_dfs() {
+			if cur_DIE.tag() != gimli::DW_TAG_subprogram {
+				continue;
+			}
+			let name = match cur_DIE.attr_value(gimli::DW_AT_name) {
+				Ok(Some(attr)) => match dwarf_info.attr_string(&unit, attr).ok().and_then(|string| string.to_string().ok().map(|s| s.to_string())) {
+					Some(name) => name,
+					None => {continue;},
+				},
+				_ => {continue;},
+			};
+			if let Some(range) = get_pc_range(cur_DIE) {
+				funcs.push((name, range));
+			}
+		}
+	}
+	funcs
+}
+
+
 fn get_pc_range<R: gimli::Reader>(func: &DebuggingInformationEntry<R>) -> Option<Range<u64>> {
 	//DwAt(0x11) = DW_AT_low_pc
 	let low_pc = match func.attr(DwAt(0x11)).unwrap() {
@@ -95,6 +218,390 @@ fn get_pc_range<R: gimli::Reader>(func: &DebuggingInformationEntry<R>) -> Option
 
 
 
+//every statement row from the line programs of all compilation units, sorted by address
+pub fn read_line_table<R: gimli::Reader>(dwarf_info: &Dwarf<R>) -> Vec<line_row> {
+	let mut rows = Vec::new();
+	let mut compilation_units = dwarf_info.units();
+	while let Ok(Some(compilation_unit)) = compilation_units.next() {
+		let unit = match dwarf_info.unit(compilation_unit) {
+			Ok(unit) => unit,
+			Err(_) => {continue;},
+		};
+		let program = match unit.line_program.clone() {
+			Some(program) => program,
+			None => {continue;},
+		};
+		let mut linerows = program.rows();
+		while let Ok(Some((prog_header, linerow))) = linerows.next_row() {
+			if !linerow.is_stmt() && !linerow.end_sequence() {
+				continue;
+			}
+			let file = match linerow.file(prog_header) {
+				Some(file_entry) => file_path(dwarf_info, &unit, prog_header, file_entry),
+				None => String::new(),
+			};
+			rows.push(line_row {
+				addr: linerow.address(),
+				file,
+				line: linerow.line().unwrap_or(0),
+				end_sequence: linerow.end_sequence(),
+			});
+		}
+	}
+	//end of one sequence can be the start of the next. The start has to win
+	rows.sort_by_key(|row| (row.addr, !row.end_sequence));
+	rows
+}
+
+//directory/name of a file in the line program header
+fn file_path<R: gimli::Reader>(dwarf_info: &Dwarf<R>, unit: &gimli::Unit<R>, header: &gimli::LineProgramHeader<R>, file: &gimli::FileEntry<R>) -> String {
+	let attr_to_string = |attr| -> Option<String> {
+		let string = dwarf_info.attr_string(unit, attr).ok()?;
+		Some(string.to_string_lossy().ok()?.into_owned())
+	};
+	let name = attr_to_string(file.path_name()).unwrap_or_default();
+	if name.starts_with('/') {
+		return name;
+	}
+	match file.directory(header).and_then(attr_to_string) {
+		Some(dir) if !dir.is_empty() => format!("{}/{}", dir, name),
+		_ => name,
+	}
+}
+
+//file and line some (file) address is in
+pub fn line_for_addr(rows: &[line_row], addr: u64) -> Option<&line_row> {
+	let idx = match rows.binary_search_by(|row| row.addr.cmp(&addr)) {
+		Ok(idx) => idx,
+		Err(0) => {return None;},
+		Err(idx) => idx - 1,
+	};
+	//binary search can land on any row with the same address. Want the last of them
+	let mut idx = idx;
+	while idx + 1 < rows.len() && rows[idx+1].addr <= addr {
+		idx += 1;
+	}
+	let row = &rows[idx];
+	if row.end_sequence {
+		return None;
+	}
+	Some(row)
+}
+
+//lowest address for a line of a file. If that line has no code, the next one that does
+//file can be just the end of the path (e.g "main.c" or "src/main.c")
+pub fn addr_for_line(rows: &Vec<line_row>, file: &str, line: u64) -> Option<u64> {
+	let mut best : Option<&line_row> = None;
+	for row in rows {
+		if row.end_sequence || row.line < line {
+			continue;
+		}
+		if row.file != file && !row.file.ends_with(&format!("/{}", file)) {
+			continue;
+		}
+		best = match best {
+			Some(cur) if (cur.line, cur.addr) <= (row.line, row.addr) => Some(cur),
+			_ => Some(row),
+		};
+	}
+	best.map(|row| row.addr)
+}
+
+
+//a variable in scope at some pc, and what it takes to read it
+#[derive(Clone)]
+pub struct var_info<R: gimli::Reader> {
+	pub location: gimli::Expression<R>,
+	//of the function its declared in. None for globals
+	pub frame_base: Option<gimli::Expression<R>>,
+	pub encoding: gimli::Encoding,
+	//of its type. Only integers and pointers (8 bytes or less) can be read
+	pub size: u64,
+	pub signed: bool,
+}
+
+//the variable called name that code at pc (file address) would see
+//locals in the innermost scope win, then parameters and locals further out, then globals
+pub fn find_variable<R: gimli::Reader>(dwarf_info: &Dwarf<R>, pc: u64, name: &str) -> Option<var_info<R>> {
+	let mut best : Option<(isize, var_info<R>)> = None;
+	let mut compilation_units = dwarf_info.units();
+	while let Ok(Some(compilation_unit)) = compilation_units.next() {
+		let unit = match dwarf_info.unit(compilation_unit) {
+			Ok(unit) => unit,
+			Err(_) => {continue;},
+		};
+		//functions and blocks the walk is currently inside. (depth, whether pc is in it, frame base)
+		let mut scopes : Vec<(isize, bool, Option<gimli::Expression<R>>)> = Vec::new();
+		let mut depth = 0;
+		let mut DIE_tree = unit.entries();
+		while let Ok(Some((delta, cur_DIE))) = DIE_tree.next_dfs() {
+			depth += delta;
+			while scopes.last().is_some_and(|scope| scope.0 >= depth) {
+				scopes.pop();
+			}
+			match cur_DIE.tag() {
+				gimli::DW_TAG_subprogram | gimli::DW_TAG_lexical_block | gimli::DW_TAG_inlined_subroutine => {
+					let contains = die_contains(dwarf_info, &unit, cur_DIE, pc);
+					let frame_base = match cur_DIE.attr_value(gimli::DW_AT_frame_base) {
+						Ok(Some(AttributeValue::Exprloc(expr))) => Some(expr),
+						//blocks use the frame base of the function theyre in
+						_ => scopes.last().and_then(|scope| scope.2.clone()),
+					};
+					scopes.push((depth, contains, frame_base));
+				},
+				gimli::DW_TAG_variable | gimli::DW_TAG_formal_parameter => {
+					if !scopes.iter().all(|scope| scope.1) || best.as_ref().is_some_and(|(best_depth, _)| *best_depth >= depth) {
+						continue;
+					}
+					let matches = match cur_DIE.attr_value(gimli::DW_AT_name) {
+						Ok(Some(attr)) => match dwarf_info.attr_string(&unit, attr) {
+							Ok(string) => string.to_string().map(|string| string == name).unwrap_or(false),
+							Err(_) => false,
+						},
+						_ => false,
+					};
+					if !matches {
+						continue;
+					}
+					//declarations (extern) and location lists arent something that can be read here
+					let location = match cur_DIE.attr_value(gimli::DW_AT_location) {
+						Ok(Some(AttributeValue::Exprloc(expr))) => expr,
+						_ => {continue;},
+					};
+					let (size, signed) = match cur_DIE.attr_value(gimli::DW_AT_type) {
+						Ok(Some(AttributeValue::UnitRef(offset))) => type_size(&unit, offset),
+						_ => (8, false),
+					};
+					best = Some((depth, var_info {
+						location,
+						frame_base: scopes.last().and_then(|scope| scope.2.clone()),
+						encoding: unit.encoding(),
+						size,
+						signed,
+					}));
+				},
+				_ => {},
+			};
+		}
+	}
+	best.map(|(_, var)| var)
+}
+
+fn die_contains<R: gimli::Reader>(dwarf_info: &Dwarf<R>, unit: &gimli::Unit<R>, die: &DebuggingInformationEntry<R>, pc: u64) -> bool {
+	let mut ranges = match dwarf_info.die_ranges(unit, die) {
+		Ok(ranges) => ranges,
+		Err(_) => {return false;},
+	};
+	while let Ok(Some(range)) = ranges.next() {
+		if range.begin <= pc && pc < range.end {
+			return true;
+		}
+	}
+	false
+}
+
+//(size, signed) of a type. Typedefs and qualifiers are looked through
+fn type_size<R: gimli::Reader>(unit: &gimli::Unit<R>, offset: gimli::UnitOffset<R::Offset>) -> (u64, bool) {
+	let mut offset = offset;
+	//limit is in case of a loop in bad debug info
+	for _ in 0..16 {
+		let mut cursor = match unit.entries_at_offset(offset) {
+			Ok(cursor) => cursor,
+			Err(_) => {break;},
+		};
+		let die = match cursor.next_dfs() {
+			Ok(Some((_, die))) => die,
+			_ => {break;},
+		};
+		match die.tag() {
+			gimli::DW_TAG_typedef | gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type | gimli::DW_TAG_restrict_type => {
+				match die.attr_value(gimli::DW_AT_type) {
+					Ok(Some(AttributeValue::UnitRef(next))) => {offset = next;},
+					//const void and the like
+					_ => {break;},
+				};
+			},
+			gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+				return (8, false);
+			},
+			_ => {
+				let size = match die.attr_value(gimli::DW_AT_byte_size) {
+					Ok(Some(attr)) => attr.udata_value().unwrap_or(8),
+					_ => 8,
+				};
+				let signed = match die.attr_value(gimli::DW_AT_encoding) {
+					Ok(Some(AttributeValue::Encoding(enc))) => enc == gimli::DW_ATE_signed || enc == gimli::DW_ATE_signed_char,
+					_ => false,
+				};
+				return (size, signed);
+			},
+		};
+	}
+	(8, false)
+}
+
+//what a function gives back, so finish knows where to look (rax or xmm0) and how to show it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ret_type {
+	Void,
+	//(size, signed)
+	Int(u64, bool),
+	//float or double, in xmm0
+	Float(u64),
+	Pointer,
+	//structs and the like. Not something rax alone can show
+	Other,
+}
+
+//return type of the function pc (file address) is in. None if DWARF doesnt know the function
+pub fn return_type<R: gimli::Reader>(dwarf_info: &Dwarf<R>, pc: u64) -> Option<ret_type> {
+	let mut compilation_units = dwarf_info.units();
+	while let Ok(Some(compilation_unit)) = compilation_units.next() {
+		let unit = match dwarf_info.unit(compilation_unit) {
+			Ok(unit) => unit,
+			Err(_) => {continue;},
+		};
+		let mut DIE_tree = unit.entries();
+		while let Ok(Some((_, cur_DIE))) = DIE_tree.next_dfs() {
+			if cur_DIE.tag() != gimli::DW_TAG_subprogram || !die_contains(dwarf_info, &unit, cur_DIE, pc) {
+				continue;
+			}
+			//no type means void
+			return Some(match cur_DIE.attr_value(gimli::DW_AT_type) {
+				Ok(Some(AttributeValue::UnitRef(offset))) => type_kind(&unit, offset),
+				_ => ret_type::Void,
+			});
+		}
+	}
+	None
+}
+
+//like type_size, but also tells floats, pointers and structs apart
+fn type_kind<R: gimli::Reader>(unit: &gimli::Unit<R>, offset: gimli::UnitOffset<R::Offset>) -> ret_type {
+	let mut offset = offset;
+	for _ in 0..16 {
+		let mut cursor = match unit.entries_at_offset(offset) {
+			Ok(cursor) => cursor,
+			Err(_) => {break;},
+		};
+		let die = match cursor.next_dfs() {
+			Ok(Some((_, die))) => die,
+			_ => {break;},
+		};
+		match die.tag() {
+			gimli::DW_TAG_typedef | gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type | gimli::DW_TAG_restrict_type => {
+				match die.attr_value(gimli::DW_AT_type) {
+					Ok(Some(AttributeValue::UnitRef(next))) => {offset = next;},
+					_ => {break;},
+				};
+			},
+			gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+				return ret_type::Pointer;
+			},
+			gimli::DW_TAG_base_type | gimli::DW_TAG_enumeration_type => {
+				let size = match die.attr_value(gimli::DW_AT_byte_size) {
+					Ok(Some(attr)) => attr.udata_value().unwrap_or(8),
+					_ => 8,
+				};
+				return match die.attr_value(gimli::DW_AT_encoding) {
+					Ok(Some(AttributeValue::Encoding(enc))) if enc == gimli::DW_ATE_float && size <= 8 => ret_type::Float(size),
+					Ok(Some(AttributeValue::Encoding(enc))) if enc == gimli::DW_ATE_float => ret_type::Other,
+					Ok(Some(AttributeValue::Encoding(enc))) => ret_type::Int(size, enc == gimli::DW_ATE_signed || enc == gimli::DW_ATE_signed_char),
+					_ => ret_type::Int(size, false),
+				};
+			},
+			_ => {
+				return ret_type::Other;
+			},
+		};
+	}
+	ret_type::Other
+}
+
+//x86_64 DWARF register numbers, as named in user_regs_struct
+pub fn dwarf_reg_name(reg: u16) -> Option<&'static str> {
+	const NAMES : [&str; 17] = [
+		"rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp",
+		"r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15", "rip",
+	];
+	NAMES.get(reg as usize).cloned()
+}
+
+//runs a location expression. Registers are the current (runtime) ones, and addresses in the expression are moved by the load bias
+pub fn eval_location<R: gimli::Reader>(expr: &gimli::Expression<R>, encoding: gimli::Encoding, frame_base: Option<u64>, cfa: Option<u64>, bias: u64,
+	regs: &HashMap<&str, u64>, read: &dyn Fn(u64) -> Option<u64>) -> Result<gimli::Location<R>, String> {
+	let mut eval = expr.clone().evaluation(encoding);
+	let mut result = eval.evaluate();
+	loop {
+		result = match result {
+			Ok(gimli::EvaluationResult::Complete) => {break;},
+			Ok(gimli::EvaluationResult::RequiresRegister { register, .. }) => {
+				let val = dwarf_reg_name(register.0).and_then(|name| regs.get(name)).ok_or(format!("Unsupported register {} in location", register.0))?;
+				eval.resume_with_register(gimli::Value::Generic(*val))
+			},
+			Ok(gimli::EvaluationResult::RequiresMemory { address, size, .. }) => {
+				let val = read(address).ok_or(format!("Cannot access memory at {:#x}", address))?;
+				let val = if size >= 8 {val} else {val & ((1 << (size as u64*8)) - 1)};
+				eval.resume_with_memory(gimli::Value::Generic(val))
+			},
+			Ok(gimli::EvaluationResult::RequiresFrameBase) => {
+				eval.resume_with_frame_base(frame_base.ok_or("No frame base to find variable with")?)
+			},
+			Ok(gimli::EvaluationResult::RequiresCallFrameCfa) => {
+				eval.resume_with_call_frame_cfa(cfa.ok_or("Could not work out the frame address (no .eh_frame info)")?)
+			},
+			Ok(gimli::EvaluationResult::RequiresRelocatedAddress(addr)) => {
+				eval.resume_with_relocated_address(addr.wrapping_add(bias))
+			},
+			Ok(_) => {return Err(String::from("Unsupported variable location"));},
+			Err(err) => {return Err(format!("Bad variable location. Error was {}", err));},
+		};
+	}
+	match eval.result().into_iter().next() {
+		Some(piece) => Ok(piece.location),
+		None => Err(String::from("Variable has been optimised out")),
+	}
+}
+
+
+//.eh_frame of the program. Tells how to find the CFA (stack pointer before the call) at any address
+pub struct call_frames {
+	eh_frame: gimli::EhFrame<EndianRcSlice<RunTimeEndian>>,
+	bases: gimli::BaseAddresses,
+}
+
+pub fn load_call_frames(path: &str) -> Option<call_frames> {
+	let bin_data = fs::read(path).ok()?;
+	let obj_file = object::read::File::parse(&*bin_data).ok()?;
+	let endian = if obj_file.is_little_endian() {RunTimeEndian::Little} else {RunTimeEndian::Big};
+	let section = obj_file.section_by_name(".eh_frame")?;
+	let data : Rc<[u8]> = Rc::from(&*section.uncompressed_data().ok()?);
+	let mut bases = gimli::BaseAddresses::default().set_eh_frame(section.address());
+	if let Some(text) = obj_file.section_by_name(".text") {
+		bases = bases.set_text(text.address());
+	}
+	Some(call_frames {
+		eh_frame: gimli::EhFrame::from(EndianRcSlice::new(data, endian)),
+		bases,
+	})
+}
+
+impl call_frames {
+	//CFA of the function pc (file address) is in, given the registers its stopped with
+	pub fn cfa(&self, pc: u64, regs: &HashMap<&str, u64>) -> Option<u64> {
+		let mut ctx = gimli::UninitializedUnwindContext::new();
+		let row = self.eh_frame.unwind_info_for_address(&self.bases, &mut ctx, pc, gimli::EhFrame::cie_from_offset).ok()?;
+		match row.cfa() {
+			gimli::CfaRule::RegisterAndOffset { register, offset } => {
+				let base = regs.get(dwarf_reg_name(register.0)?)?;
+				Some(base.wrapping_add(*offset as u64))
+			},
+			gimli::CfaRule::Expression(_) => None,
+		}
+	}
+}
+
+
 pub fn line_stuff<R: gimli::Reader<Offset=usize>>(dwarf_info: &Dwarf<R>) {
 	let incomplete_prog = dwarf_info.debug_line.program(gimli::DebugLineOffset(0 as usize),8,None,None).unwrap();
 	let (complete_prog, seqs) = incomplete_prog.sequences().unwrap();
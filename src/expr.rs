@@ -0,0 +1,434 @@
+//Small C-like expressions, for breakpoint conditions
+//	rax == 3			registers
+//	*(u32*)(rsp+8) > 10	memory, read as the type its cast to (plain * reads 8 bytes)
+//	count > 5 && i != 2	variables, when theres DWARF info
+//Everything is worked out as a 64 bit signed integer
+
+
+//where the values of registers, memory and variables come from
+pub trait ExprEnv {
+	fn reg(&self, name: &str) -> Option<u64>;
+	//8 bytes at addr
+	fn read(&self, addr: u64) -> Result<u64, String>;
+	fn var(&self, name: &str) -> Result<i64, String>;
+}
+
+//integer types that can be cast to or read through. (size in bytes, signed)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct int_type {
+	pub size: u32,
+	pub signed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum expr {
+	Num(i64),
+	Reg(String),
+	Var(String),
+	//read memory at the address, as this type
+	Deref(Box<expr>, int_type),
+	Cast(Box<expr>, int_type),
+	Unary(&'static str, Box<expr>),
+	Binary(&'static str, Box<expr>, Box<expr>),
+}
+
+//lowest precedence first
+const BINARY_OPS : [&[&str]; 10] = [
+	&["||"],
+	&["&&"],
+	&["|"],
+	&["^"],
+	&["&"],
+	&["==", "!="],
+	&["<=", ">=", "<", ">"],
+	&["<<", ">>"],
+	&["+", "-"],
+	&["*", "/", "%"],
+];
+
+//longer ones first, so "<=" isnt read as "<"
+const OPERATORS : [&str; 21] = [
+	"||", "&&", "==", "!=", "<=", ">=", "<<", ">>",
+	"|", "^", "&", "<", ">", "+", "-", "*", "/", "%", "!", "~", "(",
+];
+
+const REGISTERS : [&str; 27] = [
+	"rax", "rbx", "rcx", "rdx", "rdi", "rsi", "rbp", "rsp",
+	"r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+	"rip", "eflags", "cs", "ds", "es", "fs", "gs", "ss",
+	"fs_base", "gs_base", "orig_rax",
+];
+
+
+fn type_by_name(name: &str) -> Option<int_type> {
+	let (size, signed) = match name {
+		"u8" | "uint8_t" | "unsigned char" => (1, false),
+		"i8" | "int8_t" | "char" | "signed char" => (1, true),
+		"u16" | "uint16_t" | "unsigned short" => (2, false),
+		"i16" | "int16_t" | "short" => (2, true),
+		"u32" | "uint32_t" | "unsigned" | "unsigned int" => (4, false),
+		"i32" | "int32_t" | "int" => (4, true),
+		"u64" | "uint64_t" | "usize" | "size_t" | "unsigned long" => (8, false),
+		"i64" | "int64_t" | "isize" | "long" => (8, true),
+		_ => {return None;},
+	};
+	Some(int_type { size, signed })
+}
+
+//cuts a value down to the size of the type, then extends it back to 64 bits
+pub fn truncate(val: u64, ty: int_type) -> i64 {
+	if ty.size >= 8 {
+		return val as i64;
+	}
+	let bits = 64 - ty.size*8;
+	if ty.signed {
+		((val << bits) as i64) >> bits
+	}
+	else {
+		((val << bits) >> bits) as i64
+	}
+}
+
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+	let mut tokens = Vec::new();
+	let chars : Vec<char> = text.chars().collect();
+	let mut i = 0;
+	'outer: while i < chars.len() {
+		let c = chars[i];
+		if c.is_whitespace() {
+			i += 1;
+			continue;
+		}
+		//numbers, names (including mod::name paths) and $registers
+		if c.is_alphanumeric() || c == '_' || c == '$' {
+			let start = i;
+			i += 1;
+			while i < chars.len() {
+				if chars[i].is_alphanumeric() || chars[i] == '_' {
+					i += 1;
+				}
+				else if chars[i] == ':' && i + 2 < chars.len() && chars[i+1] == ':' {
+					i += 2;
+				}
+				else {
+					break;
+				}
+			}
+			tokens.push(chars[start..i].iter().collect());
+			continue;
+		}
+		if c == ')' {
+			tokens.push(String::from(")"));
+			i += 1;
+			continue;
+		}
+		for op in OPERATORS.iter() {
+			let op_chars : Vec<char> = op.chars().collect();
+			if chars[i..].starts_with(&op_chars) {
+				tokens.push(op.to_string());
+				i += op_chars.len();
+				continue 'outer;
+			}
+		}
+		return Err(format!("Unexpected character '{}' in expression", c));
+	}
+	Ok(tokens)
+}
+
+
+struct parser {
+	tokens: Vec<String>,
+	pos: usize,
+}
+
+impl parser {
+	fn peek(&self) -> Option<&str> {
+		self.tokens.get(self.pos).map(|tok| tok.as_str())
+	}
+
+	fn peek_at(&self, off: usize) -> Option<&str> {
+		self.tokens.get(self.pos + off).map(|tok| tok.as_str())
+	}
+
+	fn expect(&mut self, tok: &str) -> Result<(), String> {
+		if self.peek() != Some(tok) {
+			return Err(format!("Expected '{}' in expression", tok));
+		}
+		self.pos += 1;
+		Ok(())
+	}
+
+	fn binary(&mut self, level: usize) -> Result<expr, String> {
+		if level == BINARY_OPS.len() {
+			return self.unary();
+		}
+		let mut lhs = self.binary(level + 1)?;
+		while let Some(op) = self.peek().and_then(|tok| BINARY_OPS[level].iter().find(|op| **op == tok).copied()) {
+			self.pos += 1;
+			let rhs = self.binary(level + 1)?;
+			lhs = expr::Binary(op, Box::new(lhs), Box::new(rhs));
+		}
+		Ok(lhs)
+	}
+
+	//(type) or (type*) at the current position. Multi-word C types (unsigned int) are allowed
+	fn cast_type(&self) -> Option<(int_type, bool, usize)> {
+		if self.peek() != Some("(") {
+			return None;
+		}
+		let mut words = Vec::new();
+		let mut off = 1;
+		while let Some(tok) = self.peek_at(off) {
+			if tok == "*" || tok == ")" {
+				break;
+			}
+			words.push(tok);
+			off += 1;
+		}
+		let ty = type_by_name(&words.join(" "))?;
+		let pointer = self.peek_at(off) == Some("*");
+		if pointer {
+			off += 1;
+		}
+		if self.peek_at(off) != Some(")") {
+			return None;
+		}
+		Some((ty, pointer, off + 1))
+	}
+
+	fn unary(&mut self) -> Result<expr, String> {
+		match self.peek() {
+			Some("-") | Some("!") | Some("~") => {
+				let op = match self.peek() {
+					Some("-") => "-",
+					Some("!") => "!",
+					_ => "~",
+				};
+				self.pos += 1;
+				Ok(expr::Unary(op, Box::new(self.unary()?)))
+			},
+			//*(u32*)addr reads a u32. *addr reads 8 bytes
+			Some("*") => {
+				self.pos += 1;
+				if let Some((ty, true, len)) = self.cast_type() {
+					self.pos += len;
+					return Ok(expr::Deref(Box::new(self.unary()?), ty));
+				}
+				Ok(expr::Deref(Box::new(self.unary()?), int_type { size: 8, signed: false }))
+			},
+			Some("(") => {
+				if let Some((ty, pointer, len)) = self.cast_type() {
+					self.pos += len;
+					let inner = self.unary()?;
+					//a pointer cast on its own doesnt change the value
+					if pointer {
+						return Ok(inner);
+					}
+					return Ok(expr::Cast(Box::new(inner), ty));
+				}
+				self.pos += 1;
+				let inner = self.binary(0)?;
+				self.expect(")")?;
+				Ok(inner)
+			},
+			Some(_) => self.atom(),
+			None => Err(String::from("Expression ended unexpectedly")),
+		}
+	}
+
+	fn atom(&mut self) -> Result<expr, String> {
+		let tok = self.peek().unwrap_or("").to_string();
+		self.pos += 1;
+		let first = tok.chars().next().unwrap_or(' ');
+		if first.is_ascii_digit() {
+			let num = if let Some(hex) = tok.strip_prefix("0x") {
+				u64::from_str_radix(hex, 16).ok()
+			}
+			else {
+				tok.parse::<u64>().ok()
+			};
+			return match num {
+				Some(num) => Ok(expr::Num(num as i64)),
+				None => Err(format!("Invalid number {}", tok)),
+			};
+		}
+		//$rax is always the register, even if theres a variable called rax
+		if let Some(reg) = tok.strip_prefix('$') {
+			if !REGISTERS.contains(&reg) {
+				return Err(format!("Unknown register {}", reg));
+			}
+			return Ok(expr::Reg(reg.to_string()));
+		}
+		if first.is_alphabetic() || first == '_' {
+			if REGISTERS.contains(&tok.as_str()) {
+				return Ok(expr::Reg(tok));
+			}
+			return Ok(expr::Var(tok));
+		}
+		Err(format!("Unexpected '{}' in expression", tok))
+	}
+}
+
+pub fn parse_expr(text: &str) -> Result<expr, String> {
+	let mut parser = parser { tokens: tokenize(text)?, pos: 0 };
+	if parser.tokens.is_empty() {
+		return Err(String::from("Empty expression"));
+	}
+	let parsed = parser.binary(0)?;
+	if let Some(tok) = parser.peek() {
+		return Err(format!("Unexpected '{}' in expression", tok));
+	}
+	Ok(parsed)
+}
+
+pub fn eval(e: &expr, env: &dyn ExprEnv) -> Result<i64, String> {
+	match e {
+		expr::Num(num) => Ok(*num),
+		expr::Reg(name) => env.reg(name).map(|val| val as i64).ok_or(format!("Unknown register {}", name)),
+		expr::Var(name) => env.var(name),
+		expr::Deref(addr, ty) => {
+			let addr = eval(addr, env)? as u64;
+			Ok(truncate(env.read(addr)?, *ty))
+		},
+		expr::Cast(inner, ty) => Ok(truncate(eval(inner, env)? as u64, *ty)),
+		expr::Unary(op, inner) => {
+			let val = eval(inner, env)?;
+			Ok(match *op {
+				"-" => val.wrapping_neg(),
+				"!" => (val == 0) as i64,
+				_ => !val,
+			})
+		},
+		expr::Binary(op, lhs, rhs) => {
+			let lhs = eval(lhs, env)?;
+			//only work out the right side if its needed, like C
+			match *op {
+				"&&" if lhs == 0 => {return Ok(0);},
+				"||" if lhs != 0 => {return Ok(1);},
+				_ => {},
+			};
+			let rhs = eval(rhs, env)?;
+			Ok(match *op {
+				"&&" | "||" => (rhs != 0) as i64,
+				"|" => lhs | rhs,
+				"^" => lhs ^ rhs,
+				"&" => lhs & rhs,
+				"==" => (lhs == rhs) as i64,
+				"!=" => (lhs != rhs) as i64,
+				"<" => (lhs < rhs) as i64,
+				"<=" => (lhs <= rhs) as i64,
+				">" => (lhs > rhs) as i64,
+				">=" => (lhs >= rhs) as i64,
+				"<<" => lhs.wrapping_shl(rhs as u32),
+				">>" => lhs.wrapping_shr(rhs as u32),
+				"+" => lhs.wrapping_add(rhs),
+				"-" => lhs.wrapping_sub(rhs),
+				"*" => lhs.wrapping_mul(rhs),
+				"/" | "%" if rhs == 0 => {return Err(String::from("Division by zero"));},
+				"/" => lhs.wrapping_div(rhs),
+				_ => lhs.wrapping_rem(rhs),
+			})
+		},
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	//rax = 3 and rsp = 0x1000. The only memory that can be read is the word at rsp
+	struct fake_env;
+
+	impl ExprEnv for fake_env {
+		fn reg(&self, name: &str) -> Option<u64> {
+			match name {
+				"rax" => Some(3),
+				"rsp" => Some(0x1000),
+				_ => None,
+			}
+		}
+
+		fn read(&self, addr: u64) -> Result<u64, String> {
+			match addr {
+				0x1000 => Ok(0x1122_3344_5566_ff80),
+				_ => Err(format!("Cannot access memory at {:#x}", addr)),
+			}
+		}
+
+		fn var(&self, name: &str) -> Result<i64, String> {
+			match name {
+				"count" => Ok(-7),
+				_ => Err(format!("No variable {}", name)),
+			}
+		}
+	}
+
+	fn run(text: &str) -> Result<i64, String> {
+		eval(&parse_expr(text)?, &fake_env)
+	}
+
+	#[test]
+	fn precedence() {
+		assert_eq!(run("1 + 2 * 3"), Ok(7));
+		assert_eq!(run("(1 + 2) * 3"), Ok(9));
+		assert_eq!(run("10 - 4 - 3"), Ok(3));
+		assert_eq!(run("1 << 2 + 1"), Ok(8));
+		assert_eq!(run("1 | 2 & 0"), Ok(1));
+		assert_eq!(run("6 ^ 3 & 1"), Ok(7));
+		assert_eq!(run("1 < 2 == 1"), Ok(1));
+		assert_eq!(run("rax == 3 && count < 0 || 0"), Ok(1));
+		assert_eq!(run("-2 * 3"), Ok(-6));
+		assert_eq!(run("!0 + ~0"), Ok(0));
+	}
+
+	#[test]
+	fn casts() {
+		assert_eq!(run("(u8)0x1ff"), Ok(0xff));
+		assert_eq!(run("(char)0xff"), Ok(-1));
+		assert_eq!(run("(unsigned int)-1"), Ok(0xffff_ffff));
+		assert_eq!(run("(short)0x18000"), Ok(-0x8000));
+		//a pointer cast leaves the value alone
+		assert_eq!(run("(u8*)0x1ff"), Ok(0x1ff));
+	}
+
+	#[test]
+	fn memory_reads() {
+		assert_eq!(run("*(u16*)rsp"), Ok(0xff80));
+		assert_eq!(run("*(i8*)$rsp"), Ok(-128));
+		assert_eq!(run("*(u32*)(rsp + 0)"), Ok(0x5566_ff80));
+		assert_eq!(run("*rsp"), Ok(0x1122_3344_5566_ff80));
+		assert!(run("*(u8*)8").is_err());
+	}
+
+	#[test]
+	fn division_by_zero() {
+		assert_eq!(run("1 / 0"), Err(String::from("Division by zero")));
+		assert_eq!(run("5 % (rax - 3)"), Err(String::from("Division by zero")));
+		//right side isnt worked out when it doesnt need to be
+		assert_eq!(run("0 && 1 / 0"), Ok(0));
+		assert_eq!(run("1 || 1 / 0"), Ok(1));
+		assert_eq!(run("-7 / 2"), Ok(-3));
+		assert_eq!(run("-7 % 2"), Ok(-1));
+	}
+
+	#[test]
+	fn bad_expressions() {
+		assert!(parse_expr("").is_err());
+		assert!(parse_expr("1 +").is_err());
+		assert!(parse_expr("(1 + 2").is_err());
+		assert!(parse_expr("rax rbx").is_err());
+		assert!(parse_expr("$foo").is_err());
+		assert!(parse_expr("1 @ 2").is_err());
+		assert!(run("nothing").is_err());
+	}
+
+	#[test]
+	fn truncating() {
+		assert_eq!(truncate(0xfff, int_type { size: 1, signed: true }), -1);
+		assert_eq!(truncate(0xfff, int_type { size: 1, signed: false }), 0xff);
+		assert_eq!(truncate(0x8000_0000, int_type { size: 4, signed: true }), -0x8000_0000);
+		assert_eq!(truncate(u64::MAX, int_type { size: 8, signed: false }), -1);
+	}
+}
@@ -0,0 +1,38 @@
+use nix::unistd::Pid;
+
+use crate::thread::ThreadTable;
+use crate::breakpoint::bp_storage;
+use crate::launch::exe_of;
+
+
+//which process the debugger sticks with after the debugee forks
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ForkMode {
+	Parent,
+	Child,
+}
+
+//A process being debugged that isnt the current one
+//kept stopped until the user switches to it with 'inferior <num>'
+pub struct inferior {
+	pub num: usize,
+	pub pid: Pid,
+	pub threads: ThreadTable,
+	pub bp_table: bp_storage,
+	pub attached: bool,
+	pub load_bias: usize,
+}
+
+
+pub fn list_inferiors(cur_num: usize, cur_pid: Pid, others: &[inferior]) {
+	let mut all : Vec<(usize, Pid)> = others.iter().map(|inf| (inf.num, inf.pid)).collect();
+	all.push((cur_num, cur_pid));
+	all.sort();
+
+	println!("  <num>: <pid>: <program>");
+	for (num, pid) in all {
+		let marker = if num == cur_num {"*"} else {" "};
+		let prog = exe_of(pid).unwrap_or(String::from("<unknown>"));
+		println!("{} <{}>: <{}>: <{}>", marker, num, pid, prog);
+	}
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+
+use nix::unistd::{execve, chdir, dup2, Pid};
+use nix::errno::Errno;
+use nix::sys::wait::{self, WaitPidFlag};
+use nix::fcntl::{open, OFlag};
+use nix::sys::stat::Mode;
+use nix::sys::ptrace;
+
+use linux_personality::personality;
+
+use crate::thread::get_tasks;
+
+
+//Everything needed to (re)launch the debugee
+//Lives outside of the Debugger so that it survives each pass of the restart loop
+#[derive(Debug, Clone)]
+pub struct LaunchConfig {
+	pub prog: String,
+	pub args: Vec<String>,
+	//changes made to the environment inherited from the debugger
+	//Some(val) sets the variable, None unsets it
+	pub env: HashMap<String, Option<String>>,
+	pub cwd: Option<String>,
+	pub stdin: Option<String>,
+	pub stdout: Option<String>,
+	//true if stdout should be appended to rather than truncated (>> instead of >)
+	pub stdout_append: bool,
+	//turn off address space randomisation, so addresses are the same every run
+	pub disable_aslr: bool,
+}
+
+impl LaunchConfig {
+	pub fn New(prog: String) -> LaunchConfig {
+		LaunchConfig {
+			prog,
+			args: Vec::new(),
+			env: HashMap::new(),
+			cwd: None,
+			stdin: None,
+			stdout: None,
+			stdout_append: false,
+			disable_aslr: true,
+		}
+	}
+
+	//accepts both "KEY=VAL" and "KEY VAL" forms
+	pub fn set_env(&mut self, args: &[&str]) -> Result<(), ()> {
+		if args.is_empty() {
+			return Err(());
+		}
+		let (key, val) = match args[0].find('=') {
+			Some(idx) => {
+				let mut val = args[0][idx+1..].to_string();
+				for extra in &args[1..] {
+					val.push(' ');
+					val.push_str(extra);
+				}
+				(args[0][..idx].to_string(), val)
+			},
+			None => (args[0].to_string(), args[1..].join(" ")),
+		};
+		if key.is_empty() {
+			return Err(());
+		}
+		self.env.insert(key, Some(val));
+		Ok(())
+	}
+
+	pub fn unset_env(&mut self, key: &str) {
+		self.env.insert(key.to_string(), None);
+	}
+
+	//takes a list of args which may contain "< file", "> file" or ">> file"
+	//the redirections are pulled out and the remaining words become the new argv
+	pub fn set_args_and_redirects(&mut self, args: &[&str]) -> Result<(), String> {
+		let mut new_args = Vec::new();
+		let mut stdin = None;
+		let mut stdout = None;
+		let mut append = false;
+
+		let mut i = 0;
+		while i < args.len() {
+			let arg = args[i];
+			//operator can be either its own word ("> out.txt") or attached to the file (">out.txt")
+			let (op, attached) = if let Some(rest) = arg.strip_prefix(">>") {
+				(">>", rest)
+			}
+			else if let Some(rest) = arg.strip_prefix('>') {
+				(">", rest)
+			}
+			else if let Some(rest) = arg.strip_prefix('<') {
+				("<", rest)
+			}
+			else {
+				new_args.push(arg.to_string());
+				i += 1;
+				continue;
+			};
+
+			let file = if !attached.is_empty() {
+				attached.to_string()
+			}
+			else if i + 1 < args.len() {
+				i += 1;
+				args[i].to_string()
+			}
+			else {
+				return Err(format!("No file given for redirection '{}'", op));
+			};
+
+			match op {
+				"<" => {stdin = Some(file);},
+				">>" => {stdout = Some(file); append = true;},
+				_ => {stdout = Some(file); append = false;},
+			};
+			i += 1;
+		}
+
+		self.args = new_args;
+		self.stdin = stdin;
+		self.stdout = stdout;
+		self.stdout_append = append;
+		Ok(())
+	}
+
+	//the environment as it will be given to execve
+	fn build_env(&self) -> Vec<CString> {
+		let mut vars : HashMap<String, String> = std::env::vars().collect();
+		for (key, val) in &self.env {
+			match val {
+				Some(v) => {vars.insert(key.clone(), v.clone());},
+				None => {vars.remove(key);},
+			};
+		}
+		vars.iter().filter_map(|(k, v)| CString::new(format!("{}={}", k, v)).ok()).collect()
+	}
+
+	pub fn print(&self) {
+		println!("Program: {}", self.prog);
+		println!("Args: {}", self.args.join(" "));
+		match &self.cwd {
+			Some(dir) => {println!("Working directory: {}", dir);},
+			None => {println!("Working directory: <inherited>");},
+		};
+		match &self.stdin {
+			Some(file) => {println!("stdin: {}", file);},
+			None => {println!("stdin: <inherited>");},
+		};
+		match &self.stdout {
+			Some(file) => {println!("stdout: {}{}", if self.stdout_append {">> "} else {""}, file);},
+			None => {println!("stdout: <inherited>");},
+		};
+		println!("disable-randomization: {}", if self.disable_aslr {"on"} else {"off"});
+	}
+
+	pub fn print_env(&self) {
+		let mut keys : Vec<&String> = self.env.keys().collect();
+		keys.sort();
+		for key in keys {
+			match &self.env[key] {
+				Some(val) => {println!("{}={}", key, val);},
+				None => {println!("unset {}", key);},
+			};
+		}
+	}
+
+	//Called in the forked child. Never returns - either becomes the debugee or exits
+	pub fn exec_child(&self) -> ! {
+		//Child must call this to make it 'traceable' by the parent
+		if let Err(err_num) = ptrace::traceme() {
+			child_fail(&format!("PTRACE_TRACEME failed with {}", err_num));
+		}
+
+		if let Some(dir) = &self.cwd {
+			if let Err(err_num) = chdir(dir.as_str()) {
+				child_fail(&format!("Failed to change directory to {}. Error was {}", dir, err_num));
+			}
+		}
+
+		if let Some(file) = &self.stdin {
+			redirect(file, OFlag::O_RDONLY, 0);
+		}
+		if let Some(file) = &self.stdout {
+			let mode = if self.stdout_append {OFlag::O_APPEND} else {OFlag::O_TRUNC};
+			redirect(file, OFlag::O_WRONLY | OFlag::O_CREAT | mode, 1);
+		}
+
+		//disable aslr
+		if self.disable_aslr && personality(linux_personality::ADDR_NO_RANDOMIZE).is_err() {
+			child_fail("Failed to disable address space randomization");
+		}
+
+		let prog = CString::new(self.prog.clone()).unwrap();
+		let mut argv = vec!(prog.clone());
+		for arg in &self.args {
+			argv.push(CString::new(arg.clone()).unwrap());
+		}
+		let envp = self.build_env();
+
+		//runs debugee.  spawn() would fork.
+		let err_num = execve(&prog, &argv, &envp).unwrap_err();
+		child_fail(&format!("Failed to execute {}. Error was {}", self.prog, err_num));
+	}
+}
+
+//for the forked child, before it execs
+//stdout might be the users file by now, so errors go to stderr. _exit, as the debuggers own
+//buffers and exit handlers are copies, and not the childs to flush or run
+fn child_fail(msg: &str) -> ! {
+	eprintln!("{}", msg);
+	unsafe { libc::_exit(127) }
+}
+
+
+fn redirect(file: &str, flags: OFlag, target_fd: i32) {
+	let fd = match open(file, flags, Mode::from_bits_truncate(0o644)) {
+		Ok(fd) => fd,
+		Err(err_num) => {child_fail(&format!("Failed to open {} for redirection. Error was {}", file, err_num));},
+	};
+	if let Err(err_num) = dup2(fd, target_fd) {
+		child_fail(&format!("Failed to redirect to {}. Error was {}", file, err_num));
+	}
+}
+
+
+//attach to an already running process (every thread of it) and wait for it to stop
+pub fn attach(pid: Pid) -> Result<(), Errno> {
+	ptrace::attach(pid)?;
+	//attach sends a SIGSTOP. Need to wait for it to actually be stopped before touching it
+	wait::waitpid(pid, Some(WaitPidFlag::__WALL))?;
+
+	//each thread has to be attached seperately. Threads can be created while we're doing this
+	//so keep going until a pass finds nothing new
+	let mut attached = vec!(pid);
+	loop {
+		let mut found_new = false;
+		for tid in get_tasks(pid) {
+			if attached.contains(&tid) {
+				continue;
+			}
+			found_new = true;
+			attached.push(tid);
+			//thread could have exited since we listed it. Not an error
+			if ptrace::attach(tid).is_ok() {
+				wait::waitpid(tid, Some(WaitPidFlag::__WALL)).ok();
+			}
+		}
+		if !found_new {
+			break;
+		}
+	}
+	Ok(())
+}
+
+//path of the executable a process is running
+pub fn exe_of(pid: Pid) -> Option<String> {
+	match fs::read_link(format!("/proc/{}/exe", pid)) {
+		Ok(path) => Some(path.to_string_lossy().into_owned()),
+		Err(_) => None,
+	}
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::convert::TryInto;
+
+use nix::unistd::Pid;
+
+use crate::target::Target;
+use crate::auxv::{auxv_get, AT_PHDR};
+
+
+const ET_DYN: u16 = 3;
+const PT_LOAD: u32 = 1;
+const PT_PHDR: u32 = 6;
+
+//The parts of the ELF headers needed to work out where the program was loaded
+//Addresses are link-time (file) addresses, as found in the DWARF info
+#[derive(Clone, Copy, Debug)]
+pub struct elf_layout {
+	//ET_DYN executable. Gets loaded at a different address from the one it was linked at
+	pub pie: bool,
+	//lowest PT_LOAD address. The first mapping of the file is loaded here (plus the bias)
+	pub base_vaddr: u64,
+	//end of the highest PT_LOAD. Anything from base_vaddr up to here belongs to the program
+	pub end_vaddr: u64,
+	//address the program headers are loaded at. What AT_PHDR in auxv points to (plus the bias)
+	pub phdr_vaddr: Option<u64>,
+}
+
+fn read_u16(data: &[u8], off: usize) -> Option<u16> {
+	Some(u16::from_le_bytes(data.get(off..off+2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], off: usize) -> Option<u32> {
+	Some(u32::from_le_bytes(data.get(off..off+4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], off: usize) -> Option<u64> {
+	Some(u64::from_le_bytes(data.get(off..off+8)?.try_into().ok()?))
+}
+
+//only handles 64 bit little endian ELF's, like the rest of the debugger
+pub fn parse_elf_layout(path: &str) -> Option<elf_layout> {
+	let data = fs::read(path).ok()?;
+	//\x7fELF, ELFCLASS64, ELFDATA2LSB
+	if data.get(0..6)? != b"\x7fELF\x02\x01" {
+		return None;
+	}
+	let e_type = read_u16(&data, 16)?;
+	let e_phoff = read_u64(&data, 32)?;
+	let e_phentsize = read_u16(&data, 54)? as usize;
+	let e_phnum = read_u16(&data, 56)? as usize;
+
+	let mut base_vaddr = None;
+	let mut end_vaddr = 0;
+	let mut phdr_vaddr = None;
+	for i in 0..e_phnum {
+		let phdr = e_phoff as usize + i*e_phentsize;
+		let p_type = read_u32(&data, phdr)?;
+		let p_offset = read_u64(&data, phdr + 8)?;
+		let p_vaddr = read_u64(&data, phdr + 16)?;
+		let p_filesz = read_u64(&data, phdr + 32)?;
+		let p_memsz = read_u64(&data, phdr + 40)?;
+		match p_type {
+			PT_PHDR => {phdr_vaddr = Some(p_vaddr);},
+			PT_LOAD => {
+				if base_vaddr.is_none_or(|base| p_vaddr < base) {
+					base_vaddr = Some(p_vaddr);
+				}
+				end_vaddr = end_vaddr.max(p_vaddr + p_memsz);
+				//no PT_PHDR. Headers are still loaded if theyre inside a loaded segment
+				if phdr_vaddr.is_none() && p_offset <= e_phoff && e_phoff < p_offset + p_filesz {
+					phdr_vaddr = Some(p_vaddr + (e_phoff - p_offset));
+				}
+			},
+			_ => {},
+		};
+	}
+
+	Some(elf_layout {
+		pie: e_type == ET_DYN,
+		//segments are page aligned when mapped
+		base_vaddr: base_vaddr.unwrap_or(0) & !0xfff,
+		end_vaddr,
+		phdr_vaddr,
+	})
+}
+
+//how far the program has been moved from its link-time addresses
+//runtime address = file address + bias. Always 0 for non-PIE programs
+pub fn find_load_bias(target: &dyn Target, pid: Pid, path: &str, layout: &elf_layout) -> Option<u64> {
+	if !layout.pie {
+		return Some(0);
+	}
+
+	//first mapping (file offset 0) of the program, from /proc/<pid>/maps or the core file
+	//path has to be the resolved one, as thats what the maps show
+	if let Ok(real_path) = fs::canonicalize(path) {
+		let real_path = real_path.to_string_lossy();
+		for (start, offset, map_path) in target.file_mappings(pid) {
+			if offset == 0 && map_path == real_path {
+				return Some(start - layout.base_vaddr);
+			}
+		}
+	}
+
+	//fall back to where the kernel says it put the program headers
+	//(core files from other machines wont have the program at the same path)
+	let phdr_vaddr = layout.phdr_vaddr?;
+	auxv_get(&target.auxv(pid), AT_PHDR).map(|phdr| phdr - phdr_vaddr)
+}
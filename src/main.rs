@@ -1,19 +1,14 @@
-use std::env;
-use std::ffi::{CString};
-use std::fs;
-use std::borrow::Cow;
+//types are lower case and constructors are New() all over, same as breakpoint and debugger always were
+#![allow(non_camel_case_types, non_snake_case)]
 
-use nix::unistd::{fork,ForkResult,execv};
-use nix::sys::ptrace;
-use nix::errno::Errno;
+use std::env;
+use std::process;
 
-use linux_personality::personality;
+use nix::unistd::{fork,ForkResult,Pid};
+use nix::sys::wait;
 
 use rustyline::Editor;
 
-use gimli;
-use object::{Object, ObjectSection};
-
 
 mod debugger;
 mod breakpoint;
@@ -21,100 +16,139 @@ mod misc;
 mod format;
 mod dwarf_functionality;
 mod trace;
+mod launch;
+mod options;
+mod thread;
+mod inferior;
+mod prog_info;
+mod signals;
+mod load_bias;
+mod auxv;
+mod core_dump;
+mod core_file;
+mod target;
+mod script;
+mod session;
+mod expr;
+mod watchpoint;
+mod syscalls;
+mod catchpoint;
+mod dprintf;
+mod disasm;
 
 use debugger::*;
+use options::{parse_options, USAGE};
+use prog_info::ProgInfo;
+use launch::{attach, exe_of};
+use core_file::CoreFile;
+use script::Script;
+use session::Session;
 
 fn main() {
 	let args: Vec<String> = env::args().collect();
 	
-	if args.len() != 2 {
-		panic!("Correct argument usage: debugger <debugee>");
-	}
-
-	let prog_name: String = args[1].clone();
+	let opts = match parse_options(&args[1..]) {
+		Ok(opts) => opts,
+		Err(err) => {
+			panic!("{}\n{}", err, USAGE);
+		},
+	};
+	//args, env, cwd and redirections. Can be changed from inside the session, and is used on every restart
+	let mut launch = opts.launch;
 
 	//restart implementation is veyr messy. I did not consider it when implementing how I handled commands
-	//both the child and err parts dont ever really return, so that just leaves the parent
-	//it returns 0, exiting the loop if user inputs exit command
-	//if they enter a restart then it returns 1, but we dont actually need to cover that, since default is to keep restarting
-	let mut restart = true;
+	//each pass of the loop gets a process (either by forking or attaching), then hands it to a Debugger
+	//the Debugger returns what to do next - exit, restart (launch again), or swap to a process it attached to
 	//this handles command history. Must be definer outside the loop
 	let mut inputHandler = Editor::<()>::new();
+	//-x files and batch mode. Also outside the loop, so a script can restart the debugee and carry on
+	let mut script = match Script::New(&opts.scripts, opts.batch, opts.stop_on_error) {
+		Ok(script) => script,
+		Err(err) => {panic!("{}", err);},
+	};
 	
 	
 	//setting up dwarf debug info - only want to do it once, not every restart
-	let bin_data = fs::read(prog_name.clone()).unwrap();
-	let obj_file = object::read::File::parse(&*bin_data).unwrap();
-	let load_section = |id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
-		match obj_file.section_by_name(id.name()) {
-		//section has been found
-			Some(section) => {
-				//decompress it
-				//can potentially fail to decompress. If so, return empty section
-				Ok(section.uncompressed_data().unwrap_or(Cow::Borrowed(&[][..])))
-			},
-			None => {
-				//return empty section
-				Ok(Cow::Borrowed(&[][..]))
-			},
-		}
+	//only reloaded if we attach to a different program
+	let mut prog = ProgInfo::New(&launch.prog);
+	//breakpoints, signal handling and fork settings. Carried over from one Debugger to the next
+	let mut session = Session::New(&launch.prog);
+
+	let mut next = match (opts.core, opts.attach_pid) {
+		(Some(path), _) => dbg_cmd::Core(path),
+		(None, Some(pid)) => {
+			if let Err(err_num) = attach(pid) {
+				panic!("Failed to attach to process {}. Error was {}", pid, err_num);
+			}
+			dbg_cmd::Attach(pid)
+		},
+		(None, None) => dbg_cmd::Restart,
 	};
 
-	let dwarf_cow = gimli::Dwarf::load(&load_section, &load_section).unwrap();
-
-
-	//this was just grabbed from the docs. ont understand trait objects quite yet, so thisll be a placeholder
-	let borrow_section: &dyn for<'a> Fn(
-			&'a Cow<[u8]>,
-		) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-			&|section| gimli::EndianSlice::new(&*section, gimli::RunTimeEndian::Little);
-			
-	let dwarf = dwarf_cow.borrow(&borrow_section);
-
-
-
-
-	while restart == true {
-		match unsafe{fork()} {
-			
-			Ok(ForkResult::Child) => {
-				//might need to catch the result and check for errors?
-				//Child must call this to make it 'traceable' by the parent
-				ptrace::traceme();	
-				let prog = CString::new(prog_name.clone()).unwrap();
-				
-				//disable aslr
-				personality(linux_personality::ADDR_NO_RANDOMIZE).unwrap();
-				
-				//runs debugee.  spawn() would fork. 
-				execv(&prog, &[prog.clone()]);
-
+	loop {
+		let start_at = match next {
+			dbg_cmd::Start(at) => Some(at),
+			_ => None,
+		};
+		let mut dbg = match next {
+			dbg_cmd::Attach(pid) => {
+				//the program being debugged is now whatever that process is running
+				if let Some(exe) = exe_of(pid) {
+					launch.prog = exe;
+				}
+				Debugger::Attached(pid)
+			},
+			dbg_cmd::Core(path) => {
+				match CoreFile::New(&path).and_then(Debugger::Core) {
+					Ok(dbg) => dbg,
+					Err(err) => {
+						eprintln!("{}", err);
+						process::exit(1);
+					},
+				}
 			},
+			_ => Debugger::New(spawn(&launch)),
+		};
 
-			//child is type Pid
-			Ok(ForkResult::Parent {child}) => {
-				let mut dbg = Debugger::New(child);
+		//program may have changed through an attach, or an exec in the last run
+		prog.reload_if_changed(&launch.prog);
+		dbg.shown_bias = session.shown_bias.clone();
+		dbg.update_load_bias(&prog);
+		dbg.rearm(&mut session, &prog);
+		if let Some(at) = start_at {
+			dbg.start(at, &prog);
+		}
 
-				
+		next = dbg.run(&mut inputHandler, &mut script, &mut prog, &mut launch);
+		dbg.save_session(&mut session, &prog);
+		dbg.release();
 
+		if let dbg_cmd::Exit = next {
+			break;
+		}
+	}
+	//non-zero if any scripted command failed
+	process::exit(script.exit_status());
+}
 
-				if dbg.run(&mut inputHandler,&dwarf) == false  {
-					restart = false;
-				}
-				match ptrace::kill(child) {
-					Ok(_) => {},
-					Err(err_num) => {
-						//ESRCH means process is already dead
-						if err_num != Errno::ESRCH {
-							println!("Ptrace kill command failed with {}", err_num);
-						}
-					},
-				}
-			},
 
-			Err(err) => {
-				panic!("Failed to fork. Err is {}", err);
-			},
-		};
+//fork and exec the debugee, returning once its stopped at the exec
+fn spawn(launch: &launch::LaunchConfig) -> Pid {
+	match unsafe{fork()} {
+		Ok(ForkResult::Child) => {
+			//sets up argv, env, cwd, redirections then execs. Never returns
+			launch.exec_child();
+		},
+
+		//child is type Pid
+		Ok(ForkResult::Parent {child}) => {
+			//wait for child to startup. It sends signa when its finished setting up
+			wait::waitpid(child, None).ok();
+			child
+		},
+
+		Err(err) => {
+			panic!("Failed to fork. Err is {}", err);
+		},
 	}
 }
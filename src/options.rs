@@ -0,0 +1,121 @@
+use nix::unistd::Pid;
+
+use crate::launch::{LaunchConfig, exe_of};
+use crate::misc::str_to_int;
+
+pub const USAGE: &str = "Correct argument usage: debugger [--cwd <dir>] [--aslr on|off] [-x <script>]... [--batch] [--stop-on-error] [--env KEY=VAL]... [--stdin <file>] [--stdout <file>] [--] <debugee> [args...]\n\
+                         or: debugger -p <pid> [<debugee>]\n\
+                         or: debugger --core <core> <debugee>";
+
+
+//command line options given to the debugger itself
+pub struct Options {
+	pub launch: LaunchConfig,
+	//attach to this process instead of launching the debugee
+	pub attach_pid: Option<Pid>,
+	//look at this core file instead of running the debugee
+	pub core: Option<String>,
+	//command files to run at startup, in order. "-" is stdin
+	pub scripts: Vec<String>,
+	//run the scripts (or stdin) and exit, without an interactive prompt
+	pub batch: bool,
+	pub stop_on_error: bool,
+}
+
+
+//debugger [opts] -- <prog> <args...>
+//the '--' is optional. The first argument that isnt an option is taken as the program
+pub fn parse_options(args: &[String]) -> Result<Options, String> {
+	let mut cwd = None;
+	let mut env : Vec<String> = Vec::new();
+	let mut stdin = None;
+	let mut stdout = None;
+	let mut attach_pid = None;
+	let mut aslr = false;
+	let mut core = None;
+	let mut scripts = Vec::new();
+	let mut batch = false;
+	let mut stop_on_error = false;
+
+	let mut i = 0;
+	while i < args.len() {
+		let arg = args[i].as_str();
+		if arg == "--" {
+			i += 1;
+			break;
+		}
+		if !arg.starts_with('-') {
+			break;
+		}
+
+		//flags. Everything else takes a value
+		match arg {
+			"--batch" => {batch = true; i += 1; continue;},
+			"--stop-on-error" => {stop_on_error = true; i += 1; continue;},
+			_ => {},
+		};
+
+		if i + 1 >= args.len() {
+			return Err(format!("Option {} needs a value", arg));
+		}
+		let val = args[i+1].clone();
+		match arg {
+			"--cwd" => {cwd = Some(val);},
+			"--env" | "-e" => {env.push(val);},
+			"--stdin" => {stdin = Some(val);},
+			"--stdout" => {stdout = Some(val);},
+			"--core" => {core = Some(val);},
+			"-x" => {scripts.push(val);},
+			"--aslr" => {
+				match val.as_str() {
+					"on" => {aslr = true;},
+					"off" => {aslr = false;},
+					_ => {return Err(format!("--aslr needs to be on or off, not {}", val));},
+				};
+			},
+			"-p" | "--pid" => {
+				match str_to_int(&val) {
+					Some(pid) => {attach_pid = Some(Pid::from_raw(pid as i32));},
+					None => {return Err(format!("Invalid pid {}", val));},
+				};
+			},
+			_ => {return Err(format!("Unknown option {}", arg));},
+		};
+		i += 2;
+	}
+
+	let mut launch = if i < args.len() {
+		let mut tmp = LaunchConfig::New(args[i].clone());
+		tmp.args = args[i+1..].to_vec();
+		tmp
+	}
+	//when attaching the program can be worked out from the process itself
+	else if let Some(pid) = attach_pid {
+		match exe_of(pid) {
+			Some(exe) => LaunchConfig::New(exe),
+			None => {return Err(format!("Could not find the executable of process {}", pid));},
+		}
+	}
+	else {
+		return Err(String::from("No debugee specified"));
+	};
+
+	launch.cwd = cwd;
+	launch.stdin = stdin;
+	launch.stdout = stdout;
+	launch.disable_aslr = !aslr;
+	for var in env {
+		if launch.set_env(&[var.as_str()]).is_err() {
+			return Err(format!("Invalid environment variable {}", var));
+		}
+	}
+
+	Ok(Options {
+		launch,
+		attach_pid,
+		core,
+		scripts,
+		batch,
+		stop_on_error,
+	})
+}
@@ -0,0 +1,214 @@
+use std::fs;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use object::{Object, ObjectSymbol, SymbolKind};
+use rustc_demangle::demangle;
+use regex::Regex;
+
+use crate::dwarf_functionality::{load_dwarf, empty_dwarf, DwarfInfo, find_function, list_functions, line_row, read_line_table, line_for_addr, addr_for_line, call_frames, load_call_frames, find_variable, DwarfVar};
+use crate::load_bias::{parse_elf_layout, elf_layout};
+
+
+//a function (or object) from the symbol table. Addresses are file addresses
+#[derive(Clone, Debug)]
+pub struct symbol {
+	//demangled, without the hash (e.g mymod::func)
+	pub name: String,
+	//as it is in the symbol table
+	pub raw_name: String,
+	pub addr: u64,
+	pub size: u64,
+	//code rather than data
+	pub func: bool,
+}
+
+//Everything read from the program file itself, along with which file it came from
+//Kept around between restarts, and only reloaded when the program changes (attach, exec)
+pub struct ProgInfo {
+	pub path: String,
+	pub dwarf: DwarfInfo,
+	//None if the file couldnt be read as an ELF
+	pub layout: Option<elf_layout>,
+	//entry point from the ELF header. File address
+	pub entry: Option<u64>,
+	//sorted by address
+	pub symbols: Vec<symbol>,
+	//from the DWARF line programs, sorted by address
+	pub lines: Vec<line_row>,
+	//None if theres no .eh_frame
+	pub frames: Option<call_frames>,
+	//variables found by find_variable, by (pc, name). Finding one walks all the DWARF, and conditions look them up on every hit
+	var_cache: RefCell<HashMap<(u64, String), Option<DwarfVar>>>,
+}
+
+impl ProgInfo {
+	pub fn New(path: &str) -> ProgInfo {
+		let dwarf = match load_dwarf(path) {
+			Ok(dwarf) => dwarf,
+			Err(err) => {
+				println!("{}", err);
+				empty_dwarf()
+			},
+		};
+		let (entry, symbols) = read_symbols(path);
+		let lines = read_line_table(&dwarf);
+		ProgInfo {
+			path: path.to_string(),
+			dwarf,
+			layout: parse_elf_layout(path),
+			entry,
+			symbols,
+			lines,
+			frames: load_call_frames(path),
+			var_cache: RefCell::new(HashMap::new()),
+		}
+	}
+
+	pub fn reload_if_changed(&mut self, path: &str) {
+		if !self.is_file(path) {
+			*self = ProgInfo::New(path);
+		}
+	}
+
+	//the variable called name that code at pc (file address) would see
+	pub fn find_variable(&self, pc: u64, name: &str) -> Option<DwarfVar> {
+		let key = (pc, name.to_string());
+		if let Some(var) = self.var_cache.borrow().get(&key) {
+			return var.clone();
+		}
+		let var = find_variable(&self.dwarf, pc, name);
+		self.var_cache.borrow_mut().insert(key, var.clone());
+		var
+	}
+
+	//stripped programs (or ones that couldnt be read) have no line table
+	pub fn has_debug_info(&self) -> bool {
+		!self.lines.is_empty()
+	}
+
+	//whether path is the file this was loaded from. It can be named differently (relative, through a symlink)
+	pub fn is_file(&self, path: &str) -> bool {
+		if self.path == path {
+			return true;
+		}
+		match (fs::canonicalize(&self.path), fs::canonicalize(path)) {
+			(Ok(ours), Ok(theirs)) => ours == theirs,
+			_ => false,
+		}
+	}
+
+	//file address of a function. Symbol table first, then the DWARF info if its been stripped
+	pub fn lookup_function(&self, name: &str) -> Option<u64> {
+		if let Some(sym) = self.lookup_symbol(name) {
+			return Some(sym.addr);
+		}
+		find_function(&self.dwarf, name)
+	}
+
+	//matches the full name (mangled or not), or just the end of the path (func for mymod::func)
+	pub fn lookup_symbol(&self, name: &str) -> Option<&symbol> {
+		let exact = self.symbols.iter().find(|sym| sym.name == name || sym.raw_name == name);
+		if exact.is_some() {
+			return exact;
+		}
+		let suffix = format!("::{}", name);
+		self.symbols.iter().find(|sym| sym.name.ends_with(&suffix))
+	}
+
+	//first line of a function after its prologue, so locals are set up when stopped there
+	//without line info theres no way to know, so its just the start
+	pub fn after_prologue(&self, sym: &symbol) -> u64 {
+		//lines are sorted, so the functions rows start right after its address
+		let start = self.lines.partition_point(|row| row.addr <= sym.addr);
+		self.lines[start..].iter()
+			.take_while(|row| row.addr < sym.addr + sym.size)
+			.find(|row| !row.end_sequence)
+			.map_or(sym.addr, |row| row.addr)
+	}
+
+	pub fn line_for_addr(&self, addr: u64) -> Option<&line_row> {
+		line_for_addr(&self.lines, addr)
+	}
+
+	pub fn addr_for_line(&self, file: &str, line: u64) -> Option<u64> {
+		addr_for_line(&self.lines, file, line)
+	}
+
+	//text of a line of a source file. None if the file cant be read (e.g it was built somewhere else)
+	pub fn source_line(&self, file: &str, line: u64) -> Option<String> {
+		let text = fs::read_to_string(file).ok()?;
+		text.lines().nth((line as usize).checked_sub(1)?).map(|text| text.to_string())
+	}
+
+	//e.g "main+0x8 at test.c:5". Whichever parts are known
+	pub fn describe_addr(&self, addr: u64) -> String {
+		let mut desc = match self.symbol_for(addr) {
+			Some((sym, 0)) => sym.name.clone(),
+			Some((sym, offset)) => format!("{}+{:#x}", sym.name, offset),
+			None => String::from("??"),
+		};
+		if let Some(row) = self.line_for_addr(addr) {
+			let file = row.file.rsplit('/').next().unwrap_or("");
+			desc.push_str(&format!(" at {}:{}", file, row.line));
+		}
+		desc
+	}
+
+	//every function whose (demangled) name matches. Symbol table first, then any only the DWARF info knows about
+	//one per address, so aliases of the same function only count once
+	pub fn functions_matching(&self, re: &Regex) -> Vec<symbol> {
+		let mut found : Vec<symbol> = self.symbols.iter().filter(|sym| sym.func && re.is_match(&sym.name)).cloned().collect();
+		for (name, range) in list_functions(&self.dwarf) {
+			if re.is_match(&name) {
+				found.push(symbol { raw_name: name.clone(), name, addr: range.start, size: range.end - range.start, func: true });
+			}
+		}
+		found.sort_by_key(|sym| sym.addr);
+		found.dedup_by_key(|sym| sym.addr);
+		found
+	}
+
+	//symbol a file address is in, and how far into it
+	pub fn symbol_for(&self, addr: u64) -> Option<(&symbol, u64)> {
+		for sym in &self.symbols {
+			if sym.addr <= addr && addr < sym.addr + sym.size.max(1) {
+				return Some((sym, addr - sym.addr));
+			}
+		}
+		None
+	}
+}
+
+
+//entry point and text/data symbols, from both .symtab and .dynsym
+fn read_symbols(path: &str) -> (Option<u64>, Vec<symbol>) {
+	let mut symbols = Vec::new();
+	let bin_data = match fs::read(path) {
+		Ok(data) => data,
+		Err(_) => {return (None, symbols);},
+	};
+	let obj_file = match object::read::File::parse(&*bin_data) {
+		Ok(file) => file,
+		Err(_) => {return (None, symbols);},
+	};
+	for sym in obj_file.symbols().chain(obj_file.dynamic_symbols()) {
+		if sym.kind() != SymbolKind::Text && sym.kind() != SymbolKind::Data {
+			continue;
+		}
+		//undefined (imported) symbols have no address
+		if sym.address() == 0 || sym.is_undefined() {
+			continue;
+		}
+		if let Ok(name) = sym.name() {
+			if !name.is_empty() {
+				//{:#} leaves off the hash rust puts on the end
+				let demangled = format!("{:#}", demangle(name));
+				symbols.push(symbol { name: demangled, raw_name: name.to_string(), addr: sym.address(), size: sym.size(), func: sym.kind() == SymbolKind::Text });
+			}
+		}
+	}
+	symbols.sort_by_key(|sym| sym.addr);
+	symbols.dedup_by(|a, b| a.raw_name == b.raw_name && a.addr == b.addr);
+	(Some(obj_file.entry()), symbols)
+}
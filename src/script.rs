@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead};
+
+
+//Commands that come from somewhere other than the user typing them (-x files, or stdin in batch mode)
+//Lives outside of the Debugger so a script carries on across restarts
+pub struct Script {
+	//commands from -x files still to be run
+	pending: VecDeque<String>,
+	//exit once the commands run out, rather than handing over to the user
+	pub batch: bool,
+	//give up on the rest of the commands after the first one that fails
+	pub stop_on_error: bool,
+	//batch mode without any -x files reads its commands from stdin
+	from_stdin: bool,
+	//some command has failed
+	pub failed: bool,
+}
+
+impl Script {
+	pub fn New(files: &Vec<String>, batch: bool, stop_on_error: bool) -> Result<Script, String> {
+		let mut pending = VecDeque::new();
+		let mut from_stdin = batch && files.is_empty();
+		for file in files {
+			// "-x -" reads the commands from stdin
+			if file == "-" {
+				from_stdin = true;
+				continue;
+			}
+			let contents = match fs::read_to_string(file) {
+				Ok(contents) => contents,
+				Err(err) => {return Err(format!("Failed to read script {}. Error was {:?}", file, err.kind()));},
+			};
+			pending.extend(contents.lines().map(|line| line.to_string()));
+		}
+		Ok(Script {
+			pending,
+			batch,
+			stop_on_error,
+			from_stdin,
+			failed: false,
+		})
+	}
+
+	//next command to run. None once the script has nothing left
+	//blank lines and # comments are skipped
+	pub fn next_line(&mut self) -> Option<String> {
+		loop {
+			let line = match self.pending.pop_front() {
+				Some(line) => line,
+				None if self.from_stdin => {
+					let mut line = String::new();
+					match io::stdin().lock().read_line(&mut line) {
+						Ok(0) | Err(_) => {
+							self.from_stdin = false;
+							return None;
+						},
+						Ok(_) => line,
+					}
+				},
+				None => {return None;},
+			};
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			return Some(line.to_string());
+		}
+	}
+
+	//a scripted command failed. Returns true if the rest of the script should be abandoned
+	pub fn command_failed(&mut self, line: &str) -> bool {
+		self.failed = true;
+		if !self.stop_on_error {
+			return false;
+		}
+		println!("Stopping script after error in command: {}", line);
+		self.pending.clear();
+		self.from_stdin = false;
+		true
+	}
+
+	//what the debugger should exit with
+	pub fn exit_status(&self) -> i32 {
+		if self.failed {1} else {0}
+	}
+}
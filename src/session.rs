@@ -0,0 +1,78 @@
+use crate::signals::SignalTable;
+use crate::inferior::ForkMode;
+use crate::breakpoint::{Disposition, bp_group};
+use crate::watchpoint::{WatchKind, WatchTarget};
+use crate::catchpoint::CatchKind;
+use crate::dprintf::dprintf_fmt;
+
+
+//a breakpoint as remembered between runs
+#[derive(Clone, Debug)]
+pub struct saved_bp {
+	pub id: usize,
+	//if prog_relative this is a file address, and is moved to wherever the program is loaded next run
+	//otherwise (e.g in a shared library) its used as is
+	pub addr: usize,
+	pub prog_relative: bool,
+	pub enabled: bool,
+	pub condition: Option<String>,
+	//hit counts start again each run, but hits still to be ignored carry over
+	pub ignore_count: u64,
+	pub disposition: Disposition,
+	pub commands: Vec<String>,
+	pub dprintf: Option<dprintf_fmt>,
+}
+
+//a hardware breakpoint or watchpoint as remembered between runs. Addresses work the same as for saved_bp
+#[derive(Clone, Copy, Debug)]
+pub struct saved_watch {
+	pub id: usize,
+	pub addr: usize,
+	pub prog_relative: bool,
+	pub len: usize,
+	pub kind: WatchKind,
+}
+
+//Everything the user has set up that should outlive the process being debugged
+//Kept by the main loop, and handed to each new Debugger (restart, run, attach)
+pub struct Session {
+	//program the breakpoints were set in
+	pub prog: String,
+	pub breakpoints: Vec<saved_bp>,
+	//rbreak. The breakpoints in them are in breakpoints too
+	pub bp_groups: Vec<bp_group>,
+	//so ids keep going up across runs, even if the newest breakpoints were deleted
+	pub next_bp_id: usize,
+	//ids come from next_bp_id, the same as breakpoints
+	pub watchpoints: Vec<saved_watch>,
+	//watch -s. (id, what it watches, whether its address is prog_relative)
+	pub soft_watches: Vec<(usize, WatchTarget, bool)>,
+	//same indexes as the catchpoint table. Nothing in them depends on where the program is loaded
+	pub catches: Vec<Option<CatchKind>>,
+	pub signals: SignalTable,
+	pub follow_fork: ForkMode,
+	pub detach_on_fork: bool,
+	//file dprintf output goes to. None for the console
+	pub dprintf_log: Option<String>,
+	//load bias last printed, and the program it was for
+	pub shown_bias: Option<(String, usize)>,
+}
+
+impl Session {
+	pub fn New(prog: &str) -> Session {
+		Session {
+			prog: prog.to_string(),
+			breakpoints: Vec::new(),
+			bp_groups: Vec::new(),
+			next_bp_id: 0,
+			watchpoints: Vec::new(),
+			soft_watches: Vec::new(),
+			catches: Vec::new(),
+			signals: SignalTable::New(),
+			follow_fork: ForkMode::Parent,
+			detach_on_fork: true,
+			dprintf_log: None,
+			shown_bias: None,
+		}
+	}
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+
+use nix::sys::signal::Signal;
+use libc::siginfo_t;
+
+use crate::misc::str_to_int;
+
+
+//what to do when the debugee gets a signal
+//stop: give control back to the user
+//print: say that it happened
+//pass: let the debugee actually receive it when resumed
+#[derive(Clone, Copy, Debug)]
+pub struct sig_policy {
+	pub stop: bool,
+	pub print: bool,
+	pub pass: bool,
+}
+
+#[derive(Clone)]
+pub struct SignalTable {
+	policies: HashMap<Signal, sig_policy>,
+}
+
+impl SignalTable {
+	//defaults are the same as gdb's
+	//signals that happen all the time in normal programs dont stop or print
+	//SIGTRAP and SIGINT belong to the debugger, so arent passed on
+	pub fn New() -> SignalTable {
+		let mut policies = HashMap::new();
+		for sig in Signal::iterator() {
+			let policy = match sig {
+				Signal::SIGALRM | Signal::SIGURG | Signal::SIGCHLD | Signal::SIGWINCH |
+				Signal::SIGPROF | Signal::SIGIO | Signal::SIGVTALRM => {
+					sig_policy { stop: false, print: false, pass: true }
+				},
+				Signal::SIGTRAP | Signal::SIGINT => {
+					sig_policy { stop: true, print: true, pass: false }
+				},
+				_ => {
+					sig_policy { stop: true, print: true, pass: true }
+				},
+			};
+			policies.insert(sig, policy);
+		}
+		SignalTable {
+			policies,
+		}
+	}
+
+	pub fn get(&self, sig: Signal) -> sig_policy {
+		match self.policies.get(&sig) {
+			Some(policy) => *policy,
+			None => sig_policy { stop: true, print: true, pass: true },
+		}
+	}
+
+	//handle <SIG> [stop|nostop|print|noprint|pass|nopass]...
+	//stopping implies printing, and not printing implies not stopping
+	pub fn handle(&mut self, args: &[&str]) -> Result<(), String> {
+		if args.is_empty() {
+			return Err(String::from("Handle command needs to be formatted: handle <SIG> [no]stop [no]print [no]pass"));
+		}
+		let sigs : Vec<Signal> = if args[0] == "all" {
+			//the debugger relies on these, so 'all' leaves them alone
+			Signal::iterator().filter(|sig| *sig != Signal::SIGTRAP && *sig != Signal::SIGINT).collect()
+		}
+		else {
+			match parse_signal(args[0]) {
+				Some(sig) => vec!(sig),
+				None => {return Err(format!("Unknown signal {}", args[0]));},
+			}
+		};
+
+		for sig in sigs {
+			let mut policy = self.get(sig);
+			for action in &args[1..] {
+				match *action {
+					"stop" => {policy.stop = true; policy.print = true;},
+					"nostop" => {policy.stop = false;},
+					"print" => {policy.print = true;},
+					"noprint" => {policy.print = false; policy.stop = false;},
+					"pass" | "noignore" => {policy.pass = true;},
+					"nopass" | "ignore" => {policy.pass = false;},
+					_ => {return Err(format!("Unknown action {}", action));},
+				};
+			}
+			self.policies.insert(sig, policy);
+		}
+		Ok(())
+	}
+
+	pub fn print(&self, only: Option<Signal>) {
+		println!("{:<12}{:<6}{:<7}{:<6}Description", "Signal", "Stop", "Print", "Pass");
+		for sig in Signal::iterator() {
+			if only.is_some() && only != Some(sig) {
+				continue;
+			}
+			let policy = self.get(sig);
+			println!("{:<12}{:<6}{:<7}{:<6}{}", sig.as_str(), yes_no(policy.stop), yes_no(policy.print), yes_no(policy.pass), describe(sig));
+		}
+	}
+}
+
+
+fn yes_no(val: bool) -> &'static str {
+	if val {"Yes"} else {"No"}
+}
+
+//accepts SIGUSR1, USR1, sigusr1 or the signal number
+pub fn parse_signal(name: &str) -> Option<Signal> {
+	if let Some(num) = str_to_int(name) {
+		return Signal::try_from(num as i32).ok();
+	}
+	let upper = name.to_uppercase();
+	let full = if upper.starts_with("SIG") {upper} else {format!("SIG{}", upper)};
+	Signal::from_str(&full).ok()
+}
+
+//e.g "Segmentation fault"
+pub fn describe(sig: Signal) -> String {
+	unsafe {
+		let desc = libc::strsignal(sig as i32);
+		if desc.is_null() {
+			return String::new();
+		}
+		CStr::from_ptr(desc).to_string_lossy().into_owned()
+	}
+}
+
+
+//si_code meanings. Some are shared by every signal, the rest depend on which signal it is
+fn decode_si_code(sig: Signal, code: i32) -> (&'static str, &'static str) {
+	match code {
+		0 => {return ("SI_USER", "sent by kill");},
+		0x80 => {return ("SI_KERNEL", "sent by the kernel");},
+		-1 => {return ("SI_QUEUE", "sent by sigqueue");},
+		-2 => {return ("SI_TIMER", "POSIX timer expired");},
+		-3 => {return ("SI_MESGQ", "message queue state changed");},
+		-4 => {return ("SI_ASYNCIO", "AIO completed");},
+		-5 => {return ("SI_SIGIO", "queued SIGIO");},
+		-6 => {return ("SI_TKILL", "sent by tkill/tgkill");},
+		_ => {},
+	};
+
+	match (sig, code) {
+		(Signal::SIGSEGV, 1) => ("SEGV_MAPERR", "address not mapped to object"),
+		(Signal::SIGSEGV, 2) => ("SEGV_ACCERR", "invalid permissions for mapped object"),
+		(Signal::SIGSEGV, 3) => ("SEGV_BNDERR", "failed address bound checks"),
+		(Signal::SIGSEGV, 4) => ("SEGV_PKUERR", "access denied by memory protection keys"),
+		(Signal::SIGBUS, 1) => ("BUS_ADRALN", "invalid address alignment"),
+		(Signal::SIGBUS, 2) => ("BUS_ADRERR", "nonexistent physical address"),
+		(Signal::SIGBUS, 3) => ("BUS_OBJERR", "object specific hardware error"),
+		(Signal::SIGBUS, 4) => ("BUS_MCEERR_AR", "hardware memory error consumed on a machine check"),
+		(Signal::SIGBUS, 5) => ("BUS_MCEERR_AO", "hardware memory error detected in process"),
+		(Signal::SIGILL, 1) => ("ILL_ILLOPC", "illegal opcode"),
+		(Signal::SIGILL, 2) => ("ILL_ILLOPN", "illegal operand"),
+		(Signal::SIGILL, 3) => ("ILL_ILLADR", "illegal addressing mode"),
+		(Signal::SIGILL, 4) => ("ILL_ILLTRP", "illegal trap"),
+		(Signal::SIGILL, 5) => ("ILL_PRVOPC", "privileged opcode"),
+		(Signal::SIGILL, 6) => ("ILL_PRVREG", "privileged register"),
+		(Signal::SIGILL, 7) => ("ILL_COPROC", "coprocessor error"),
+		(Signal::SIGILL, 8) => ("ILL_BADSTK", "internal stack error"),
+		(Signal::SIGFPE, 1) => ("FPE_INTDIV", "integer divide by zero"),
+		(Signal::SIGFPE, 2) => ("FPE_INTOVF", "integer overflow"),
+		(Signal::SIGFPE, 3) => ("FPE_FLTDIV", "floating-point divide by zero"),
+		(Signal::SIGFPE, 4) => ("FPE_FLTOVF", "floating-point overflow"),
+		(Signal::SIGFPE, 5) => ("FPE_FLTUND", "floating-point underflow"),
+		(Signal::SIGFPE, 6) => ("FPE_FLTRES", "floating-point inexact result"),
+		(Signal::SIGFPE, 7) => ("FPE_FLTINV", "floating-point invalid operation"),
+		(Signal::SIGFPE, 8) => ("FPE_FLTSUB", "subscript out of range"),
+		(Signal::SIGTRAP, 1) => ("TRAP_BRKPT", "process breakpoint"),
+		(Signal::SIGTRAP, 2) => ("TRAP_TRACE", "process trace trap"),
+		(Signal::SIGTRAP, 3) => ("TRAP_BRANCH", "process taken branch trap"),
+		(Signal::SIGTRAP, 4) => ("TRAP_HWBKPT", "hardware breakpoint/watchpoint"),
+		(Signal::SIGCHLD, 1) => ("CLD_EXITED", "child has exited"),
+		(Signal::SIGCHLD, 2) => ("CLD_KILLED", "child was killed"),
+		(Signal::SIGCHLD, 3) => ("CLD_DUMPED", "child terminated abnormally"),
+		(Signal::SIGCHLD, 4) => ("CLD_TRAPPED", "traced child has trapped"),
+		(Signal::SIGCHLD, 5) => ("CLD_STOPPED", "child has stopped"),
+		(Signal::SIGCHLD, 6) => ("CLD_CONTINUED", "stopped child has continued"),
+		_ => ("", "unknown"),
+	}
+}
+
+//si_code, and whichever of the fault address / sender that signal carries
+pub fn describe_siginfo(sig: Signal, info: &siginfo_t) -> String {
+	let (code_name, code_desc) = decode_si_code(sig, info.si_code);
+	let mut desc = if code_name.is_empty() {
+		format!("si_code {}", info.si_code)
+	}
+	else {
+		format!("si_code {} ({})", code_name, code_desc)
+	};
+
+	match sig {
+		Signal::SIGSEGV | Signal::SIGBUS | Signal::SIGILL | Signal::SIGFPE => {
+			let addr = unsafe { info.si_addr() } as usize;
+			desc.push_str(&format!(", fault address {:#x}", addr));
+		},
+		_ => {
+			//sent by another process
+			if info.si_code == 0 || info.si_code == -6 {
+				let pid = unsafe { info.si_pid() };
+				let uid = unsafe { info.si_uid() };
+				desc.push_str(&format!(", sent by pid {} (uid {})", pid, uid));
+			}
+		},
+	};
+	desc
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn signal_names() {
+		assert_eq!(parse_signal("SIGUSR1"), Some(Signal::SIGUSR1));
+		assert_eq!(parse_signal("usr1"), Some(Signal::SIGUSR1));
+		assert_eq!(parse_signal("sigsegv"), Some(Signal::SIGSEGV));
+		assert_eq!(parse_signal("11"), Some(Signal::SIGSEGV));
+		assert_eq!(parse_signal("SIGNOPE"), None);
+		assert_eq!(parse_signal("999"), None);
+	}
+
+	#[test]
+	fn defaults() {
+		let table = SignalTable::New();
+		let alrm = table.get(Signal::SIGALRM);
+		assert!(!alrm.stop && !alrm.print && alrm.pass);
+		let trap = table.get(Signal::SIGTRAP);
+		assert!(trap.stop && trap.print && !trap.pass);
+		let segv = table.get(Signal::SIGSEGV);
+		assert!(segv.stop && segv.print && segv.pass);
+	}
+
+	#[test]
+	fn handle_actions() {
+		let mut table = SignalTable::New();
+		table.handle(&["SIGUSR1", "nostop", "noprint", "nopass"]).unwrap();
+		let usr1 = table.get(Signal::SIGUSR1);
+		assert!(!usr1.stop && !usr1.print && !usr1.pass);
+		//stop brings print back with it
+		table.handle(&["usr1", "stop"]).unwrap();
+		let usr1 = table.get(Signal::SIGUSR1);
+		assert!(usr1.stop && usr1.print && !usr1.pass);
+		//and noprint takes stop away
+		table.handle(&["usr1", "noprint", "ignore"]).unwrap();
+		let usr1 = table.get(Signal::SIGUSR1);
+		assert!(!usr1.stop && !usr1.print && !usr1.pass);
+	}
+
+	#[test]
+	fn handle_all_leaves_debugger_signals() {
+		let mut table = SignalTable::New();
+		table.handle(&["all", "nostop", "pass"]).unwrap();
+		assert!(!table.get(Signal::SIGSEGV).stop);
+		assert!(table.get(Signal::SIGTRAP).stop);
+		assert!(!table.get(Signal::SIGINT).pass);
+	}
+
+	#[test]
+	fn handle_errors() {
+		let mut table = SignalTable::New();
+		assert!(table.handle(&[]).is_err());
+		assert!(table.handle(&["SIGNOPE", "stop"]).is_err());
+		assert!(table.handle(&["SIGUSR1", "sometimes"]).is_err());
+	}
+}
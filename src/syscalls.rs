@@ -0,0 +1,185 @@
+use nix::errno::Errno;
+use nix::unistd::Pid;
+
+
+//x86_64 syscall numbers. Index into NAMES is the number
+const NAMES : [&str; 335] = [
+	"read", "write", "open", "close", "stat", "fstat", "lstat", "poll",
+	"lseek", "mmap", "mprotect", "munmap", "brk", "rt_sigaction", "rt_sigprocmask", "rt_sigreturn",
+	"ioctl", "pread64", "pwrite64", "readv", "writev", "access", "pipe", "select",
+	"sched_yield", "mremap", "msync", "mincore", "madvise", "shmget", "shmat", "shmctl",
+	"dup", "dup2", "pause", "nanosleep", "getitimer", "alarm", "setitimer", "getpid",
+	"sendfile", "socket", "connect", "accept", "sendto", "recvfrom", "sendmsg", "recvmsg",
+	"shutdown", "bind", "listen", "getsockname", "getpeername", "socketpair", "setsockopt", "getsockopt",
+	"clone", "fork", "vfork", "execve", "exit", "wait4", "kill", "uname",
+	"semget", "semop", "semctl", "shmdt", "msgget", "msgsnd", "msgrcv", "msgctl",
+	"fcntl", "flock", "fsync", "fdatasync", "truncate", "ftruncate", "getdents", "getcwd",
+	"chdir", "fchdir", "rename", "mkdir", "rmdir", "creat", "link", "unlink",
+	"symlink", "readlink", "chmod", "fchmod", "chown", "fchown", "lchown", "umask",
+	"gettimeofday", "getrlimit", "getrusage", "sysinfo", "times", "ptrace", "getuid", "syslog",
+	"getgid", "setuid", "setgid", "geteuid", "getegid", "setpgid", "getppid", "getpgrp",
+	"setsid", "setreuid", "setregid", "getgroups", "setgroups", "setresuid", "getresuid", "setresgid",
+	"getresgid", "getpgid", "setfsuid", "setfsgid", "getsid", "capget", "capset", "rt_sigpending",
+	"rt_sigtimedwait", "rt_sigqueueinfo", "rt_sigsuspend", "sigaltstack", "utime", "mknod", "uselib", "personality",
+	"ustat", "statfs", "fstatfs", "sysfs", "getpriority", "setpriority", "sched_setparam", "sched_getparam",
+	"sched_setscheduler", "sched_getscheduler", "sched_get_priority_max", "sched_get_priority_min", "sched_rr_get_interval", "mlock", "munlock", "mlockall",
+	"munlockall", "vhangup", "modify_ldt", "pivot_root", "_sysctl", "prctl", "arch_prctl", "adjtimex",
+	"setrlimit", "chroot", "sync", "acct", "settimeofday", "mount", "umount2", "swapon",
+	"swapoff", "reboot", "sethostname", "setdomainname", "iopl", "ioperm", "create_module", "init_module",
+	"delete_module", "get_kernel_syms", "query_module", "quotactl", "nfsservctl", "getpmsg", "putpmsg", "afs_syscall",
+	"tuxcall", "security", "gettid", "readahead", "setxattr", "lsetxattr", "fsetxattr", "getxattr",
+	"lgetxattr", "fgetxattr", "listxattr", "llistxattr", "flistxattr", "removexattr", "lremovexattr", "fremovexattr",
+	"tkill", "time", "futex", "sched_setaffinity", "sched_getaffinity", "set_thread_area", "io_setup", "io_destroy",
+	"io_getevents", "io_submit", "io_cancel", "get_thread_area", "lookup_dcookie", "epoll_create", "epoll_ctl_old", "epoll_wait_old",
+	"remap_file_pages", "getdents64", "set_tid_address", "restart_syscall", "semtimedop", "fadvise64", "timer_create", "timer_settime",
+	"timer_gettime", "timer_getoverrun", "timer_delete", "clock_settime", "clock_gettime", "clock_getres", "clock_nanosleep", "exit_group",
+	"epoll_wait", "epoll_ctl", "tgkill", "utimes", "vserver", "mbind", "set_mempolicy", "get_mempolicy",
+	"mq_open", "mq_unlink", "mq_timedsend", "mq_timedreceive", "mq_notify", "mq_getsetattr", "kexec_load", "waitid",
+	"add_key", "request_key", "keyctl", "ioprio_set", "ioprio_get", "inotify_init", "inotify_add_watch", "inotify_rm_watch",
+	"migrate_pages", "openat", "mkdirat", "mknodat", "fchownat", "futimesat", "newfstatat", "unlinkat",
+	"renameat", "linkat", "symlinkat", "readlinkat", "fchmodat", "faccessat", "pselect6", "ppoll",
+	"unshare", "set_robust_list", "get_robust_list", "splice", "tee", "sync_file_range", "vmsplice", "move_pages",
+	"utimensat", "epoll_pwait", "signalfd", "timerfd_create", "eventfd", "fallocate", "timerfd_settime", "timerfd_gettime",
+	"accept4", "signalfd4", "eventfd2", "epoll_create1", "dup3", "pipe2", "inotify_init1", "preadv",
+	"pwritev", "rt_tgsigqueueinfo", "perf_event_open", "recvmmsg", "fanotify_init", "fanotify_mark", "prlimit64", "name_to_handle_at",
+	"open_by_handle_at", "clock_adjtime", "syncfs", "sendmmsg", "setns", "getcpu", "process_vm_readv", "process_vm_writev",
+	"kcmp", "finit_module", "sched_setattr", "sched_getattr", "renameat2", "seccomp", "getrandom", "memfd_create",
+	"kexec_file_load", "bpf", "execveat", "userfaultfd", "membarrier", "mlock2", "copy_file_range", "preadv2",
+	"pwritev2", "pkey_mprotect", "pkey_alloc", "pkey_free", "statx", "io_pgetevents", "rseq",
+];
+//numbers from 424 on. Everything added since then has the same number on every arch
+const NEWER_BASE: u64 = 424;
+const NEWER : [&str; 39] = [
+	"pidfd_send_signal", "io_uring_setup", "io_uring_enter", "io_uring_register", "open_tree", "move_mount", "fsopen", "fsconfig",
+	"fsmount", "fspick", "pidfd_open", "clone3", "close_range", "openat2", "pidfd_getfd", "faccessat2",
+	"process_madvise", "epoll_pwait2", "mount_setattr", "quotactl_fd", "landlock_create_ruleset", "landlock_add_rule", "landlock_restrict_self", "memfd_secret",
+	"process_mrelease", "futex_waitv", "set_mempolicy_home_node", "cachestat", "fchmodat2", "map_shadow_stack", "futex_wake", "futex_wait",
+	"futex_requeue", "statmount", "listmount", "lsm_get_self_attr", "lsm_set_self_attr", "lsm_list_modules", "mseal",
+];
+
+//how the arguments of the common syscalls are shown
+//	d int, l long, u unsigned, x hex (pointers and flags), o octal (modes), s C string
+//anything not in here gets all 6 arguments in hex
+const SIGNATURES : [(&str, &str); 96] = [
+	("read", "dxu"), ("write", "dxu"), ("open", "sxo"), ("close", "d"),
+	("stat", "sx"), ("fstat", "dx"), ("lstat", "sx"), ("poll", "xud"),
+	("lseek", "dld"), ("mmap", "xuxxdx"), ("mprotect", "xux"), ("munmap", "xu"),
+	("brk", "x"), ("rt_sigaction", "dxxu"), ("rt_sigprocmask", "dxxu"), ("ioctl", "dxx"),
+	("pread64", "dxul"), ("pwrite64", "dxul"), ("readv", "dxd"), ("writev", "dxd"),
+	("access", "so"), ("pipe", "x"), ("madvise", "xud"), ("dup", "d"),
+	("dup2", "dd"), ("nanosleep", "xx"), ("socket", "ddd"), ("connect", "dxu"),
+	("accept", "dxx"), ("sendto", "dxuxxu"), ("recvfrom", "dxuxxx"), ("bind", "dxu"),
+	("listen", "dd"), ("clone", "xxxxx"), ("execve", "sxx"), ("exit", "d"),
+	("wait4", "dxxx"), ("kill", "dd"), ("fcntl", "ddx"), ("fsync", "d"),
+	("truncate", "sl"), ("ftruncate", "dl"), ("getcwd", "xu"), ("chdir", "s"),
+	("fchdir", "d"), ("rename", "ss"), ("mkdir", "so"), ("rmdir", "s"),
+	("creat", "so"), ("link", "ss"), ("unlink", "s"), ("symlink", "ss"),
+	("readlink", "sxu"), ("chmod", "so"), ("fchmod", "do"), ("umask", "o"),
+	("arch_prctl", "dx"), ("tkill", "dd"), ("futex", "xdux"), ("getdents64", "dxu"),
+	("set_tid_address", "x"), ("clock_gettime", "dx"), ("exit_group", "d"), ("tgkill", "ddd"),
+	("openat", "dsxo"), ("mkdirat", "dso"), ("newfstatat", "dsxx"), ("unlinkat", "dsx"),
+	("renameat", "dsds"), ("readlinkat", "dsxu"), ("fchmodat", "dso"), ("faccessat", "dso"),
+	("set_robust_list", "xu"), ("pipe2", "xx"), ("dup3", "ddx"), ("prlimit64", "ddxx"),
+	("getrandom", "xux"), ("execveat", "dsxxx"), ("statx", "dsxux"), ("rseq", "xuxx"),
+	("close_range", "uux"), ("openat2", "dsxu"), ("faccessat2", "dsox"), ("clone3", "xu"),
+	("getpid", ""), ("getppid", ""), ("gettid", ""), ("getuid", ""),
+	("geteuid", ""), ("getgid", ""), ("getegid", ""), ("fork", ""),
+	("vfork", ""), ("sched_yield", ""), ("pause", ""), ("rt_sigreturn", ""),
+];
+
+//syscalls that return an address, rather than a count or fd
+const RETURNS_ADDR : [&str; 4] = ["mmap", "mremap", "brk", "shmat"];
+//strings are cut off after this many bytes
+const MAX_STRING: usize = 64;
+
+
+pub fn syscall_name(nr: u64) -> String {
+	let name = if nr < NAMES.len() as u64 {
+		Some(NAMES[nr as usize])
+	}
+	else {
+		nr.checked_sub(NEWER_BASE).and_then(|idx| NEWER.get(idx as usize)).copied()
+	};
+	match name {
+		Some(name) => name.to_string(),
+		None => format!("syscall_{}", nr),
+	}
+}
+
+//accepts the name or the number
+pub fn syscall_number(name: &str) -> Option<u64> {
+	if let Ok(nr) = name.parse::<u64>() {
+		return Some(nr);
+	}
+	if let Some(nr) = NAMES.iter().position(|other| *other == name) {
+		return Some(nr as u64);
+	}
+	NEWER.iter().position(|other| *other == name).map(|idx| NEWER_BASE + idx as u64)
+}
+
+//PTRACE_GET_SYSCALL_INFO (Linux 5.3+). Whether a syscall stop is on the way in (true) or out
+//struct ptrace_syscall_info starts with the op, then the entry or exit details (not needed, the registers have them)
+pub fn syscall_entering(tid: Pid) -> Option<bool> {
+	let mut info = [0u64; 11];
+	let res = unsafe { libc::ptrace(libc::PTRACE_GET_SYSCALL_INFO, tid.as_raw(), std::mem::size_of_val(&info), info.as_mut_ptr()) };
+	if res < 0 {
+		return None;
+	}
+	match info[0] & 0xff {
+		1 => Some(true),
+		2 => Some(false),
+		_ => None,
+	}
+}
+
+//NUL terminated string in the process. read gives 8 bytes at a time
+fn read_string(addr: u64, read: &dyn Fn(u64) -> Option<u64>) -> Option<String> {
+	let mut bytes = Vec::new();
+	while bytes.len() < MAX_STRING {
+		let word = read(addr + bytes.len() as u64)?;
+		for byte in word.to_le_bytes().iter() {
+			if *byte == 0 {
+				return Some(format!("{:?}", String::from_utf8_lossy(&bytes)));
+			}
+			bytes.push(*byte);
+		}
+	}
+	Some(format!("{:?}...", String::from_utf8_lossy(&bytes[..MAX_STRING])))
+}
+
+//write(1, 0x5555555560a0, 6)
+pub fn format_call(nr: u64, args: [u64; 6], read: &dyn Fn(u64) -> Option<u64>) -> String {
+	let name = syscall_name(nr);
+	let signature = match SIGNATURES.iter().find(|(other, _)| *other == name) {
+		Some((_, signature)) => *signature,
+		None => "xxxxxx",
+	};
+	let shown : Vec<String> = signature.chars().zip(args.iter()).map(|(kind, arg)| {
+		match kind {
+			//ints only use the low half of the register
+			'd' => format!("{}", *arg as i32),
+			'l' => format!("{}", *arg as i64),
+			'u' => format!("{}", arg),
+			'o' => format!("{:#o}", arg),
+			's' if *arg == 0 => String::from("NULL"),
+			's' => read_string(*arg, read).unwrap_or(format!("{:#x}", arg)),
+			_ => format!("{:#x}", arg),
+		}
+	}).collect();
+	format!("{}({})", name, shown.join(", "))
+}
+
+//-4095 to -1 are errors (-errno). Anything else is a real result
+pub fn format_return(nr: u64, ret: u64) -> String {
+	let ret = ret as i64;
+	if (-4095..0).contains(&ret) {
+		let errno = Errno::from_i32(-ret as i32);
+		return format!("-1 {:?} ({})", errno, errno.desc());
+	}
+	if RETURNS_ADDR.contains(&syscall_name(nr).as_str()) {
+		format!("{:#x}", ret)
+	}
+	else {
+		format!("{}", ret)
+	}
+}
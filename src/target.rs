@@ -0,0 +1,81 @@
+use std::fs;
+
+use nix::errno::Errno;
+use nix::unistd::Pid;
+use nix::sys::ptrace;
+use libc::user_regs_struct;
+use core::ffi::c_void;
+
+use crate::core_file::CoreFile;
+use crate::auxv::read_auxv;
+
+
+//Where the state being looked at comes from - a live process through ptrace, or a core file
+//Only covers reading. Anything that changes or runs the process needs it to be live
+pub trait Target {
+	fn read_mem(&self, tid: Pid, addr: usize) -> Result<u64, Errno>;
+	fn get_regs(&self, tid: Pid) -> Result<user_regs_struct, Errno>;
+	fn auxv(&self, pid: Pid) -> Vec<(u64, u64)>;
+	//(start, file offset, path) of every file backed mapping
+	fn file_mappings(&self, pid: Pid) -> Vec<(u64, u64, String)>;
+}
+
+
+pub struct LiveTarget;
+
+impl Target for LiveTarget {
+	fn read_mem(&self, tid: Pid, addr: usize) -> Result<u64, Errno> {
+		match ptrace::read(tid, addr as *mut c_void) {
+			Ok(mem_val) => Ok(mem_val as u64),
+			Err(err_num) => Err(err_num),
+		}
+	}
+
+	fn get_regs(&self, tid: Pid) -> Result<user_regs_struct, Errno> {
+		ptrace::getregs(tid)
+	}
+
+	fn auxv(&self, pid: Pid) -> Vec<(u64, u64)> {
+		read_auxv(pid)
+	}
+
+	fn file_mappings(&self, pid: Pid) -> Vec<(u64, u64, String)> {
+		let mut mappings = Vec::new();
+		let maps = match fs::read_to_string(format!("/proc/{}/maps", pid)) {
+			Ok(maps) => maps,
+			Err(_) => {return mappings;},
+		};
+		for line in maps.lines() {
+			//start-end perms offset dev inode path
+			let fields : Vec<&str> = line.split_whitespace().collect();
+			if fields.len() < 6 || !fields[5].starts_with('/') {
+				continue;
+			}
+			let start = fields[0].split('-').next().and_then(|start| u64::from_str_radix(start, 16).ok());
+			let offset = u64::from_str_radix(fields[2], 16).ok();
+			if let (Some(start), Some(offset)) = (start, offset) {
+				mappings.push((start, offset, fields[5].to_string()));
+			}
+		}
+		mappings
+	}
+}
+
+
+impl Target for CoreFile {
+	fn read_mem(&self, _tid: Pid, addr: usize) -> Result<u64, Errno> {
+		CoreFile::read_mem(self, addr)
+	}
+
+	fn get_regs(&self, tid: Pid) -> Result<user_regs_struct, Errno> {
+		CoreFile::get_regs(self, tid)
+	}
+
+	fn auxv(&self, _pid: Pid) -> Vec<(u64, u64)> {
+		self.auxv.clone()
+	}
+
+	fn file_mappings(&self, _pid: Pid) -> Vec<(u64, u64, String)> {
+		self.files.iter().map(|file| (file.start, file.offset, file.path.clone())).collect()
+	}
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use nix::unistd::Pid;
+use nix::sys::signal::Signal;
+use libc::user_regs_struct;
+
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ThreadState {
+	Stopped,
+	Running,
+	//clone event has been seen but not the new threads initial SIGSTOP yet
+	//cant touch it with ptrace until that arrives
+	Starting,
+}
+
+#[derive(Clone, Copy)]
+pub struct thread_info {
+	pub tid: Pid,
+	//what the user refers to the thread as. Never reused
+	pub num: usize,
+	pub state: ThreadState,
+	//registers as of the last stop. None while running
+	pub regs: Option<user_regs_struct>,
+	//signal that arrived while we were stopping this thread. Given back to it when it is resumed
+	pub pending_sig: Option<Signal>,
+	//signal the thread last stopped with. Only a SIGTRAP can mean its sitting past a breakpoint
+	pub stop_sig: Option<Signal>,
+}
+
+
+//every thread of the debugee, and which one commands (regs, mem, si) act on
+pub struct ThreadTable {
+	pub threads: BTreeMap<Pid, thread_info>,
+	pub selected: Pid,
+	next_num: usize,
+}
+
+impl ThreadTable {
+	pub fn New(leader: Pid) -> ThreadTable {
+		let mut table = ThreadTable {
+			threads: BTreeMap::new(),
+			selected: leader,
+			next_num: 1,
+		};
+		table.add(leader, ThreadState::Stopped);
+		table
+	}
+
+	pub fn add(&mut self, tid: Pid, state: ThreadState) -> usize {
+		if let Some(thread) = self.threads.get(&tid) {
+			return thread.num;
+		}
+		let num = self.next_num;
+		self.next_num += 1;
+		self.threads.insert(tid, thread_info {
+			tid,
+			num,
+			state,
+			regs: None,
+			pending_sig: None,
+			stop_sig: None,
+		});
+		num
+	}
+
+	pub fn remove(&mut self, tid: Pid) {
+		self.threads.remove(&tid);
+		//selected thread has gone. Fall back to whichever is left
+		if self.selected == tid {
+			if let Some(tid) = self.threads.keys().next() {
+				self.selected = *tid;
+			}
+		}
+	}
+
+	pub fn get(&mut self, tid: Pid) -> Option<&mut thread_info> {
+		self.threads.get_mut(&tid)
+	}
+
+	pub fn contains(&self, tid: Pid) -> bool {
+		self.threads.contains_key(&tid)
+	}
+
+	pub fn select(&mut self, num: usize) -> Result<Pid, ()> {
+		for thread in self.threads.values() {
+			if thread.num == num {
+				self.selected = thread.tid;
+				return Ok(thread.tid);
+			}
+		}
+		Err(())
+	}
+
+	pub fn tids(&self) -> Vec<Pid> {
+		self.threads.keys().cloned().collect()
+	}
+
+	pub fn selected_num(&self) -> usize {
+		match self.threads.get(&self.selected) {
+			Some(thread) => thread.num,
+			None => 0,
+		}
+	}
+
+	pub fn list(&self) {
+		println!("  <num>: <tid>: <state>: <rip>");
+		for thread in self.threads.values() {
+			let marker = if thread.tid == self.selected {"*"} else {" "};
+			match thread.regs {
+				Some(regs) => {
+					println!("{} <{}>: <{}>: <{:?}>: <{:#x}>", marker, thread.num, thread.tid, thread.state, regs.rip);
+				},
+				None => {
+					println!("{} <{}>: <{}>: <{:?}>", marker, thread.num, thread.tid, thread.state);
+				},
+			};
+		}
+	}
+}
+
+
+//all thread ids of a process, from /proc/<pid>/task
+pub fn get_tasks(pid: Pid) -> Vec<Pid> {
+	let mut tasks = Vec::new();
+	let dir = match fs::read_dir(format!("/proc/{}/task", pid)) {
+		Ok(dir) => dir,
+		Err(_) => {return tasks;},
+	};
+	for entry in dir.flatten() {
+		if let Ok(tid) = entry.file_name().to_string_lossy().parse::<i32>() {
+			tasks.push(Pid::from_raw(tid));
+		}
+	}
+	tasks
+}
+
+//thread group (process) a thread belongs to, from /proc/<tid>/status
+pub fn tgid_of(tid: Pid) -> Option<Pid> {
+	let status = match fs::read_to_string(format!("/proc/{}/status", tid)) {
+		Ok(status) => status,
+		Err(_) => {return None;},
+	};
+	for line in status.lines() {
+		if let Some(val) = line.strip_prefix("Tgid:") {
+			return val.trim().parse::<i32>().ok().map(Pid::from_raw);
+		}
+	}
+	None
+}
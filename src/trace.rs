@@ -3,6 +3,7 @@ use serde_json::to_writer;
 use std::collections::HashMap;
 
 use nix::sys::{ptrace,wait};
+use nix::sys::wait::WaitPidFlag;
 
 use std::fs::File;
 use std::io::{BufReader,BufRead,Write,Read};
@@ -91,7 +92,7 @@ impl<'a> Trace<'a> {
 	pub fn trace_init(dbg: &mut Debugger) {
 		//create the actual trace
 		let mut trace_var = Trace::New();
-		let regs = match ptrace::getregs(dbg.m_pid) {
+		let regs = match ptrace::getregs(dbg.cur_tid()) {
 			Ok(val) => val,
 			Err(err_num) => {
 				println!("Failed to retrieve registers with ptrace.\n Error code was {}", err_num);
@@ -100,9 +101,8 @@ impl<'a> Trace<'a> {
 		};
 		let mut regs = regs_to_dict(regs);
 
-		if dbg.bp_table.contains((regs["rip"] - 1) as usize) {
-			regs.insert("rip", regs["rip"] - 1);
-		}
+		//saved as a file address, so the snapshot still lines up if the program gets loaded somewhere else
+		regs.insert("rip", dbg.to_file(regs["rip"] as usize) as u64);
 		trace_var.set_trace_regs(regs);
 
 		let addr_maps = get_heap_and_stack(dbg);
@@ -154,15 +154,17 @@ impl<'a> Trace<'a> {
 		//need to do all the linking done in the setup funcs
 		//so break where user breaks and continue till there - kinda shitty sol
 		//but dont have breaking via function implented yet so
-		let trace_regs = trace.get_trace_regs();
-		let mut bp = breakpoint::New(dbg.m_pid, trace_regs["rip"] as usize);
+		let rip = dbg.to_runtime(trace.get_trace_regs()["rip"] as usize);
+		trace.regs.insert("rip", rip as u64);
+		let tid = dbg.cur_tid();
+		let mut bp = breakpoint::New(tid, rip);
 		bp.enable().unwrap();
-		ptrace::cont(dbg.m_pid, None);
-		wait::waitpid(dbg.m_pid, None);
+		ptrace::cont(tid, None).ok();
+		wait::waitpid(tid, Some(WaitPidFlag::__WALL)).ok();
 		bp.disable().unwrap();
 
 		//set the regs
-		ptrace::setregs(dbg.m_pid, dict_to_regs(trace.get_trace_regs()));
+		ptrace::setregs(tid, dict_to_regs(trace.get_trace_regs())).ok();
 
 		let addr_maps = get_heap_and_stack(dbg);
 		
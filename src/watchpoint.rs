@@ -0,0 +1,303 @@
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+
+use nix::errno::Errno;
+use nix::unistd::Pid;
+
+
+//where u_debugreg is in struct user (x86_64). DR0-DR7 follow each other
+const DEBUGREG_OFFSET: usize = 848;
+const DR6: usize = 6;
+const DR7: usize = 7;
+//x86 has 4 address registers (DR0-DR3)
+pub const NUM_SLOTS: usize = 4;
+
+//what sets a debug register off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+	//hbreak. Fires before the instruction runs
+	Exec,
+	//watch. Only stops if the value actually changed
+	Write,
+	//rwatch. x86 cant watch reads alone, so its an access watch that ignores writes
+	Read,
+	//awatch
+	Access,
+}
+
+impl WatchKind {
+	pub fn name(&self) -> &'static str {
+		match self {
+			WatchKind::Exec => "hw breakpoint",
+			WatchKind::Write => "watchpoint",
+			WatchKind::Read => "read watchpoint",
+			WatchKind::Access => "acc watchpoint",
+		}
+	}
+
+	//RW bits of DR7
+	fn rw_bits(&self) -> u64 {
+		match self {
+			WatchKind::Exec => 0b00,
+			WatchKind::Write => 0b01,
+			WatchKind::Read | WatchKind::Access => 0b11,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct watchpoint {
+	//from the same ids as breakpoints, so the debug register its in doesnt matter to the user
+	pub id: usize,
+	pub addr: usize,
+	//1, 2, 4 or 8 bytes. Always 1 for Exec
+	pub len: usize,
+	pub kind: WatchKind,
+	//what was there when it was set, or last fired. Decides if a write changed anything
+	pub old_value: u64,
+}
+
+//LEN bits of DR7
+fn len_bits(len: usize) -> u64 {
+	match len {
+		2 => 0b01,
+		4 => 0b11,
+		8 => 0b10,
+		_ => 0b00,
+	}
+}
+
+//only the low len bytes of a memory read belong to a watchpoint
+pub fn mask_value(val: u64, len: usize) -> u64 {
+	if len >= 8 {val} else {val & ((1 << (len*8)) - 1)}
+}
+
+fn peek_user(tid: Pid, reg: usize) -> Result<u64, Errno> {
+	Errno::clear();
+	let val = unsafe { libc::ptrace(libc::PTRACE_PEEKUSER, tid.as_raw(), DEBUGREG_OFFSET + reg*8, 0) };
+	//-1 can be a real value. errno says if it failed
+	if val == -1 && Errno::last() != Errno::UnknownErrno {
+		return Err(Errno::last());
+	}
+	Ok(val as u64)
+}
+
+fn poke_user(tid: Pid, reg: usize, val: u64) -> Result<(), Errno> {
+	let res = unsafe { libc::ptrace(libc::PTRACE_POKEUSER, tid.as_raw(), DEBUGREG_OFFSET + reg*8, val) };
+	Errno::result(res).map(drop)
+}
+
+
+//The 4 debug registers. Every thread of the process gets the same settings
+//Index into slots is the debug register. The user refers to them by the id in the watchpoint
+pub struct hw_slots {
+	pub slots: [Option<watchpoint>; NUM_SLOTS],
+}
+
+impl hw_slots {
+	pub fn New() -> hw_slots {
+		hw_slots {
+			slots: [None; NUM_SLOTS],
+		}
+	}
+
+	//takes the first free debug register. Doesnt touch the process, apply does that
+	pub fn insert(&mut self, wp: watchpoint) -> Result<usize, String> {
+		if wp.len != 1 && wp.len != 2 && wp.len != 4 && wp.len != 8 {
+			return Err(String::from("Hardware watchpoints can only be 1, 2, 4 or 8 bytes long"));
+		}
+		if !wp.addr.is_multiple_of(wp.len) {
+			return Err(format!("Hardware watchpoints of {} bytes have to be {} byte aligned", wp.len, wp.len));
+		}
+		if self.slots.iter().flatten().any(|other| other.addr == wp.addr && other.kind == wp.kind) {
+			return Err(format!("There is already a {} at that address", wp.kind.name()));
+		}
+		match self.slots.iter().position(|slot| slot.is_none()) {
+			Some(idx) => {
+				self.slots[idx] = Some(wp);
+				Ok(idx)
+			},
+			None => Err(format!("All {} debug registers are in use", NUM_SLOTS)),
+		}
+	}
+
+	//debug register the watchpoint with this id is in
+	pub fn slot_of(&self, id: usize) -> Option<usize> {
+		self.slots.iter().position(|slot| slot.is_some_and(|wp| wp.id == id))
+	}
+
+	pub fn remove(&mut self, id: usize) -> Result<(),()> {
+		match self.slot_of(id) {
+			Some(idx) => {
+				self.slots[idx] = None;
+				Ok(())
+			},
+			None => Err(()),
+		}
+	}
+
+	fn dr7(&self) -> u64 {
+		let mut dr7 = 0;
+		for (idx, slot) in self.slots.iter().enumerate() {
+			if let Some(wp) = slot {
+				//local enable, then the type and length of the slot
+				dr7 |= 1 << (idx*2);
+				dr7 |= (wp.kind.rw_bits() | (len_bits(wp.len) << 2)) << (16 + idx*4);
+			}
+		}
+		dr7
+	}
+
+	//puts the slots into a threads debug registers
+	//DR7 goes off first so a half set up slot can never fire
+	pub fn apply(&self, tid: Pid) -> Result<(), Errno> {
+		poke_user(tid, DR7, 0)?;
+		for (idx, slot) in self.slots.iter().enumerate() {
+			if let Some(wp) = slot {
+				poke_user(tid, idx, wp.addr as u64)?;
+			}
+		}
+		let dr7 = self.dr7();
+		if dr7 != 0 {
+			poke_user(tid, DR7, dr7)?;
+		}
+		Ok(())
+	}
+
+	//turns off one slot in one thread, so it can be stepped past (see apply to put it back)
+	pub fn suspend(&self, tid: Pid, idx: usize) -> Result<(), Errno> {
+		poke_user(tid, DR7, self.dr7() & !(1 << (idx*2)))
+	}
+
+	//whether a debug register has gone off in the thread, without clearing it like hit does
+	pub fn fired(&self, tid: Pid) -> bool {
+		peek_user(tid, DR6).is_ok_and(|dr6| dr6 & 0xf != 0)
+	}
+
+	//which slot made the thread stop, from DR6. Clears DR6, as the kernel leaves the bits set
+	pub fn hit(&self, tid: Pid) -> Option<usize> {
+		let dr6 = peek_user(tid, DR6).ok()?;
+		if dr6 & 0xf == 0 {
+			return None;
+		}
+		poke_user(tid, DR6, 0).ok()?;
+		//a slot thats been emptied since still counts, so the trap isnt taken for a signal
+		(0..NUM_SLOTS).find(|idx| dr6 & (1 << idx) != 0)
+	}
+}
+
+
+//what a software watchpoint looks at
+#[derive(Clone, Debug)]
+pub enum WatchTarget {
+	//(address, length). Any length, unlike the debug registers
+	Region(usize, usize),
+	//value of an expression (see expr.rs)
+	Expr(String),
+}
+
+//watch -s. Checked after every instruction, so slow, but has no limits on how many or how big
+#[derive(Clone, Debug)]
+pub struct soft_watch {
+	pub id: usize,
+	pub target: WatchTarget,
+	//bytes as of the last check. None if they couldnt be read
+	pub old_value: Option<Vec<u8>>,
+}
+
+impl soft_watch {
+	pub fn describe(&self) -> String {
+		match &self.target {
+			WatchTarget::Region(addr, len) => format!("{:#x} ({} bytes)", addr, len),
+			WatchTarget::Expr(text) => text.clone(),
+		}
+	}
+}
+
+//up to 8 bytes is shown as a number, anything bigger as the bytes
+pub fn format_watched(bytes: &[u8]) -> String {
+	if bytes.len() <= 8 {
+		let mut word = [0u8; 8];
+		word[..bytes.len()].copy_from_slice(bytes);
+		return format!("{:#x}", u64::from_le_bytes(word));
+	}
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ")
+}
+
+//a block of a threads memory. Through /proc, so big regions dont take a ptrace call per word
+pub fn read_bytes(tid: Pid, addr: usize, len: usize) -> Option<Vec<u8>> {
+	let mem = File::open(format!("/proc/{}/mem", tid)).ok()?;
+	let mut bytes = vec![0u8; len];
+	mem.read_exact_at(&mut bytes, addr as u64).ok()?;
+	Some(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn wp(id: usize, addr: usize, len: usize, kind: WatchKind) -> watchpoint {
+		watchpoint { id, addr, len, kind, old_value: 0 }
+	}
+
+	#[test]
+	fn dr7_encoding() {
+		let mut hw = hw_slots::New();
+		assert_eq!(hw.dr7(), 0);
+		hw.insert(wp(0, 0x1000, 1, WatchKind::Exec)).unwrap();
+		//L0, RW0 = 00, LEN0 = 00
+		assert_eq!(hw.dr7(), 0b1);
+		hw.insert(wp(1, 0x2000, 4, WatchKind::Write)).unwrap();
+		//L1, RW1 = 01, LEN1 = 11
+		assert_eq!(hw.dr7(), 0b1 | 0b100 | (0b1101 << 20));
+		hw.insert(wp(2, 0x3000, 8, WatchKind::Read)).unwrap();
+		hw.insert(wp(3, 0x4000, 2, WatchKind::Access)).unwrap();
+		//L2 and L3. RW2 = 11, LEN2 = 10 (8 bytes). RW3 = 11, LEN3 = 01
+		assert_eq!(hw.dr7(), 0b0101_0101 | (0b1101 << 20) | (0b1011 << 24) | (0b0111 << 28));
+	}
+
+	#[test]
+	fn freed_slots_are_reused() {
+		let mut hw = hw_slots::New();
+		for id in 0..NUM_SLOTS {
+			assert_eq!(hw.insert(wp(id, 0x1000 + id*8, 8, WatchKind::Write)), Ok(id));
+		}
+		assert!(hw.insert(wp(9, 0x2000, 8, WatchKind::Write)).is_err());
+		//removed by id, not by slot. The new one goes in the freed slot but keeps its own id
+		assert!(hw.remove(1).is_ok());
+		assert!(hw.remove(1).is_err());
+		assert_eq!(hw.insert(wp(9, 0x2000, 8, WatchKind::Write)), Ok(1));
+		assert_eq!(hw.slot_of(9), Some(1));
+		assert_eq!(hw.slot_of(1), None);
+	}
+
+	#[test]
+	fn insert_checks() {
+		let mut hw = hw_slots::New();
+		assert!(hw.insert(wp(0, 0x1000, 3, WatchKind::Write)).is_err());
+		assert!(hw.insert(wp(0, 0x1000, 16, WatchKind::Write)).is_err());
+		//has to be aligned to its own length
+		assert!(hw.insert(wp(0, 0x1002, 4, WatchKind::Write)).is_err());
+		assert!(hw.insert(wp(0, 0x1004, 8, WatchKind::Write)).is_err());
+		assert!(hw.insert(wp(0, 0x1001, 1, WatchKind::Write)).is_ok());
+		assert!(hw.insert(wp(1, 0x1002, 2, WatchKind::Write)).is_ok());
+		//same address is fine as long as its a different kind
+		assert!(hw.insert(wp(2, 0x1002, 2, WatchKind::Write)).is_err());
+		assert!(hw.insert(wp(2, 0x1002, 2, WatchKind::Access)).is_ok());
+	}
+
+	#[test]
+	fn masking() {
+		assert_eq!(mask_value(0x1122_3344_5566_7788, 1), 0x88);
+		assert_eq!(mask_value(0x1122_3344_5566_7788, 2), 0x7788);
+		assert_eq!(mask_value(0x1122_3344_5566_7788, 4), 0x5566_7788);
+		assert_eq!(mask_value(0x1122_3344_5566_7788, 8), 0x1122_3344_5566_7788);
+	}
+
+	#[test]
+	fn watched_bytes() {
+		assert_eq!(format_watched(&[0x34, 0x12]), "0x1234");
+		assert_eq!(format_watched(&[0; 9]), "00 00 00 00 00 00 00 00 00");
+	}
+}